@@ -0,0 +1,106 @@
+// Program blocks
+// USE switches between blocks, each with its own location counter.
+// Pass 1 counts every block from 0 (the default block from START),
+// then the blocks get laid out one after another in order of first use.
+#[derive(Debug, Clone)]
+pub struct Block {
+    name: String,     // "" for the default block
+    counter: i32,     // location counter, saved while another block is in use
+    max_counter: i32, // highest address reached, ORG can go back and forth
+    offset: i32,      // added to block addresses to get real addresses
+}
+
+impl Block {
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockTable {
+    blocks: Vec<Block>,
+    current: usize, // number of the block in use
+}
+
+impl Default for BlockTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockTable {
+    // starts out with just the default block
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![Block {
+                name: String::new(),
+                counter: 0,
+                max_counter: 0,
+                offset: 0,
+            }],
+            current: 0,
+        }
+    }
+
+    pub fn blocks(&self) -> &Vec<Block> {
+        &self.blocks
+    }
+
+    // number of the block in use
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // Switches to a block, making it if it's new
+    // takes the counters of the block we're leaving,
+    // returns the counters of the block we switched to
+    pub fn use_block(&mut self, name: &str, counter: i32, max_counter: i32) -> (i32, i32) {
+        self.save(counter, max_counter);
+
+        self.current = match self.blocks.iter().position(|block| block.name == name) {
+            Some(number) => number,
+            None => {
+                self.blocks.push(Block {
+                    name: name.to_string(),
+                    counter: 0,
+                    max_counter: 0,
+                    offset: 0,
+                });
+                self.blocks.len() - 1
+            }
+        };
+
+        let block = &self.blocks[self.current];
+        (block.counter, block.max_counter)
+    }
+
+    // remembers the counters of the block in use
+    pub fn save(&mut self, counter: i32, max_counter: i32) {
+        let block = &mut self.blocks[self.current];
+        block.counter = counter;
+        block.max_counter = max_counter;
+    }
+
+    // Lays the blocks out after pass 1, in the order they were first used
+    // the default block already holds real addresses, so it stays put
+    // returns the address just past the last block
+    pub fn assign_addresses(&mut self) -> i32 {
+        let mut end = 0;
+        for (number, block) in self.blocks.iter_mut().enumerate() {
+            block.offset = if number == 0 { 0 } else { end };
+            block.max_counter = block.max_counter.saturating_add(block.offset);
+            end = block.max_counter;
+        }
+        end
+    }
+
+    // Puts every block back at its beginning for pass 2,
+    // this time counting in real addresses
+    pub fn reset(&mut self) {
+        for block in &mut self.blocks {
+            block.counter = block.offset;
+            block.max_counter = block.offset;
+        }
+        self.current = 0;
+    }
+}
@@ -1,69 +1,261 @@
 // Data Record structs and methods
-#[derive(Default, Debug)]
+use crate::errors::Diagnostics;
+use crate::listing::Listing;
+use crate::symbols::{Symbol, SymbolTable};
+use std::fmt;
+
+// A text record holds at most 30 bytes (60 hex characters) of object code
+pub const MAX_TEXT_LENGTH: usize = 30;
+
+// Which way a modification record adjusts the field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sign {
+    Plus,
+    Minus,
+}
+
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sign::Plus => write!(f, "+"),
+            Sign::Minus => write!(f, "-"),
+        }
+    }
+}
+
+// One record of an object program
+// Display writes the record the way it appears in the .obj file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Header {
+        name: String,
+        start_address: i32,
+        length: i32,
+    },
+    Text {
+        start_address: i32,
+        object_code: Vec<u8>,
+    },
+    Modification {
+        address: i32,
+        half_bytes: i32,
+        sign: Sign,
+        symbol: Option<String>,
+    },
+    End {
+        first_instruction: Option<i32>,
+    },
+    Define {
+        symbols: Vec<(String, i32)>,
+    },
+    Refer {
+        symbols: Vec<String>,
+    },
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::Header {
+                name,
+                start_address,
+                length,
+            } => write!(f, "H{:<6}{:06X}{:06X}", name, start_address, length),
+            Record::Text {
+                start_address,
+                object_code,
+            } => {
+                write!(f, "T{:06X}{:02X}", start_address, object_code.len())?;
+                for byte in object_code {
+                    write!(f, "{:02X}", byte)?;
+                }
+                Ok(())
+            }
+            Record::Modification {
+                address,
+                half_bytes,
+                sign,
+                symbol,
+            } => {
+                write!(f, "M{:06X}{:02X}", address, half_bytes)?;
+                if let Some(symbol) = symbol {
+                    write!(f, "{}{}", sign, symbol)?;
+                }
+                Ok(())
+            }
+            Record::End { first_instruction } => match first_instruction {
+                Some(address) => write!(f, "E{:06X}", address),
+                None => write!(f, "E"),
+            },
+            Record::Define { symbols } => {
+                write!(f, "D")?;
+                for (name, address) in symbols {
+                    write!(f, "{:<6}{:06X}", name, address)?;
+                }
+                Ok(())
+            }
+            Record::Refer { symbols } => {
+                write!(f, "R")?;
+                for name in symbols {
+                    write!(f, "{:<6}", name)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// All the records of a program, in the order they are written
+#[derive(Default, Debug, Clone)]
 pub struct ObjectData {
-    head_record: String,
-    end_record: String,
-    text_records: Vec<String>,
-    mod_records: Vec<String>,
+    records: Vec<Record>,
 }
 
 impl ObjectData {
     pub fn new() -> Self {
         Self {
-            head_record: String::new(),
-            end_record: String::new(),
-            text_records: Vec::new(),
-            mod_records: Vec::new(),
+            records: Vec::new(),
         }
     }
-    pub fn head_record(&self) -> &str {
-        &self.head_record
+    pub fn records(&self) -> &Vec<Record> {
+        &self.records
     }
-    pub fn end_record(&self) -> &str {
-        &self.end_record
+    pub fn add_record(&mut self, record: Record) {
+        self.records.push(record);
     }
-    pub fn mod_records(&self) -> &Vec<String> {
-        &self.mod_records
+    // puts records straight after the header, or at the start if there isn't one
+    pub fn insert_after_header(&mut self, records: impl IntoIterator<Item = Record>) {
+        let position = self
+            .records
+            .iter()
+            .position(|r| matches!(r, Record::Header { .. }))
+            .map_or(0, |index| index + 1);
+        self.records.splice(position..position, records);
     }
-    pub fn text_records(&self) -> &Vec<String> {
-        &self.text_records
+    // adds the records of another section to the end
+    pub fn append(&mut self, other: ObjectData) {
+        self.records.extend(other.records);
     }
-    pub fn set_head_record(&mut self, record: String) {
-        self.head_record = record;
+    // Adds object code starting at address, packing it onto the end of
+    // the last text record when it carries straight on from it.
+    // A gap (RESB, RESW...) or a full record starts a new one.
+    pub fn add_text(&mut self, address: i32, object_code: &[u8]) {
+        let mut address = address;
+        let mut remaining = object_code;
+
+        while !remaining.is_empty() {
+            if let Some(Record::Text {
+                start_address,
+                object_code,
+            }) = self.records.last_mut()
+            {
+                let room = MAX_TEXT_LENGTH - object_code.len();
+                let contiguous = *start_address + object_code.len() as i32 == address;
+                if contiguous && room > 0 {
+                    let taken = room.min(remaining.len());
+                    object_code.extend_from_slice(&remaining[..taken]);
+                    remaining = &remaining[taken..];
+                    address += taken as i32;
+                    continue;
+                }
+            }
+
+            let taken = MAX_TEXT_LENGTH.min(remaining.len());
+            self.records.push(Record::Text {
+                start_address: address,
+                object_code: remaining[..taken].to_vec(),
+            });
+            remaining = &remaining[taken..];
+            address += taken as i32;
+        }
+    }
+    pub fn head_record(&self) -> Option<&Record> {
+        self.records
+            .iter()
+            .find(|r| matches!(r, Record::Header { .. }))
+    }
+    pub fn end_record(&self) -> Option<&Record> {
+        self.records
+            .iter()
+            .find(|r| matches!(r, Record::End { .. }))
     }
-    pub fn set_end_record(&mut self, record: String) {
-        self.end_record = record;
+    pub fn text_records(&self) -> Vec<&Record> {
+        self.records
+            .iter()
+            .filter(|r| matches!(r, Record::Text { .. }))
+            .collect()
     }
-    pub fn add_mod_records(&mut self, record: String) {
-        self.mod_records.push(record);
+    pub fn mod_records(&self) -> Vec<&Record> {
+        self.records
+            .iter()
+            .filter(|r| matches!(r, Record::Modification { .. }))
+            .collect()
     }
-    pub fn add_text_records(&mut self, record: String) {
-        self.text_records.push(record);
+}
+
+// One record per line
+impl fmt::Display for ObjectData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for record in &self.records {
+            writeln!(f, "{}", record)?;
+        }
+        Ok(())
     }
 }
 
-pub struct ModRecordData {
-    starting_address: i32,
-    mod_length: i32,
-    symbol: String,
+// A fully assembled program, along with its symbols
+// and any warnings found on the way
+#[derive(Debug)]
+pub struct ObjectProgram {
+    object_data: ObjectData,
+    symbol_tables: Vec<SymbolTable>, // one per control section
+    listing: Listing,
+    warnings: Diagnostics,
 }
 
-impl ModRecordData {
-    pub fn new(starting_address: i32, mod_length: i32, symbol: String) -> Self {
+impl ObjectProgram {
+    pub fn new(
+        object_data: ObjectData,
+        symbol_tables: Vec<SymbolTable>,
+        listing: Listing,
+        warnings: Diagnostics,
+    ) -> Self {
         Self {
-            starting_address,
-            mod_length,
-            symbol,
+            object_data,
+            symbol_tables,
+            listing,
+            warnings,
         }
     }
-
-    pub fn starting_address(&self) -> &i32 {
-        &self.starting_address
+    pub fn object_data(&self) -> &ObjectData {
+        &self.object_data
+    }
+    // symbols of the first control section, the main program
+    pub fn symbol_table(&self) -> Option<&SymbolTable> {
+        self.symbol_tables.first()
+    }
+    pub fn symbol_tables(&self) -> &Vec<SymbolTable> {
+        &self.symbol_tables
+    }
+    // every exported symbol, section by section
+    pub fn exports(&self) -> Vec<&Symbol> {
+        self.symbol_tables
+            .iter()
+            .flat_map(|symbol_table| symbol_table.symbols())
+            .filter(|symbol| symbol.exported())
+            .collect()
     }
-    pub fn mod_length(&self) -> &i32 {
-        &self.mod_length
+    pub fn listing(&self) -> &Listing {
+        &self.listing
     }
-    pub fn symbol(&self) -> &str {
-        &self.symbol
+    pub fn warnings(&self) -> &Diagnostics {
+        &self.warnings
+    }
+}
+
+// The contents of the .obj file
+impl fmt::Display for ObjectProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.object_data)
     }
 }
@@ -1,31 +1,32 @@
 pub fn is_directive(directive: &str) -> bool {
-    match directive {
-        "START" => {
-            return true;
-        }
-        "END" => {
-            return true;
-        }
-        "RESB" => {
-            return true;
-        }
-        "RESW" => {
-            return true;
-        }
-        "RESR" => {
-            return true;
-        }
-        "BYTE" => {
-            return true;
-        }
-        "WORD" => {
-            return true;
-        }
-        "EXPORTS" => {
-            return true;
-        }
-        _ => {
-            return false;
-        }
-    }
+    matches!(
+        directive,
+        "START"
+            | "END"
+            | "RESB"
+            | "RESW"
+            | "RESR"
+            | "REAL"
+            | "BYTE"
+            | "WORD"
+            | "EXPORTS"
+            | "SET"
+            | "EQU"
+            | "ORG"
+            | "LTORG"
+            | "BASE"
+            | "NOBASE"
+            | "USE"
+            | "CSECT"
+            | "EXTDEF"
+            | "EXTREF"
+    )
+}
+
+// directives that only exist on SIC/XE
+pub fn is_xe_directive(directive: &str) -> bool {
+    matches!(
+        directive,
+        "BASE" | "NOBASE" | "USE" | "CSECT" | "EXTDEF" | "EXTREF" | "RESR" | "REAL"
+    )
 }
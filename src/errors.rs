@@ -0,0 +1,279 @@
+// Error type for the assembler
+// Every error knows where it came from (file, line, column)
+// and which token caused it, so the user can go fix it.
+// Errors are collected into Diagnostics so one run reports everything.
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+// The different things that can go wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Usage,
+    UnknownTarget,
+    FileRead,
+    FileWrite,
+    MemoryOutOfBounds,
+    MissingInstruction,
+    MissingLabel,
+    DuplicateSymbol,
+    MissingOperand,
+    InvalidOperand,
+    InvalidExpression,
+    InvalidIndexRegister,
+    UnknownRegister,
+    InvalidFormat,
+    DisplacementOutOfRange,
+    RequiresXe,
+    ExternalReference,
+    IllegalRelocation,
+    DivisionByZero,
+    WordOutOfRange,
+    UnknownInstruction,
+    UndefinedSymbol,
+    MissingProgramName,
+    InvalidProgramName,
+    MissingStart,
+    DuplicateStart,
+    // warnings
+    MissingEnd,
+    AfterEnd,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ErrorKind::Usage => "Usage is sic_assembler [--target sic|xe] <filename>",
+            ErrorKind::UnknownTarget => "unknown target, use sic or xe",
+            ErrorKind::FileRead => "could not read file. Please ensure that file exists and that you have permission to open it.",
+            ErrorKind::FileWrite => "could not write the object file",
+            ErrorKind::MemoryOutOfBounds => "memory out of bounds",
+            ErrorKind::MissingInstruction => "label is not followed by an instruction or directive",
+            ErrorKind::MissingLabel => "directive needs a label",
+            ErrorKind::DuplicateSymbol => "symbol is already defined",
+            ErrorKind::MissingOperand => "missing operand",
+            ErrorKind::InvalidOperand => "invalid operand",
+            ErrorKind::InvalidExpression => "invalid expression",
+            ErrorKind::InvalidIndexRegister => "only X can be used as an index register",
+            ErrorKind::UnknownRegister => "unknown register",
+            ErrorKind::InvalidFormat => "only format 3 instructions can be extended to format 4",
+            ErrorKind::DisplacementOutOfRange => "address is out of range of a format 3 displacement",
+            ErrorKind::RequiresXe => "only available on SIC/XE, try --target xe",
+            ErrorKind::ExternalReference => {
+                "external symbols can only be used in format 4 instructions and WORD"
+            }
+            ErrorKind::IllegalRelocation => {
+                "relative terms must cancel out in pairs, leaving an absolute or relative value"
+            }
+            ErrorKind::DivisionByZero => "division by zero",
+            ErrorKind::WordOutOfRange => "value doesn't fit in a 24 bit word",
+            ErrorKind::UnknownInstruction => "unknown instruction",
+            ErrorKind::UndefinedSymbol => "undefined symbol",
+            ErrorKind::MissingProgramName => "no program name included",
+            ErrorKind::InvalidProgramName => {
+                "program name must be at most 6 letters and digits"
+            }
+            ErrorKind::MissingStart => "starting address not assigned, maybe you didn't use START?",
+            ErrorKind::DuplicateStart => "starting address was already defined, maybe you called START twice?",
+            ErrorKind::MissingEnd => "no END directive found",
+            ErrorKind::AfterEnd => "statement after END is ignored",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    kind: ErrorKind,
+    severity: Severity,
+    file: String,
+    line: usize,   // 1-based, 0 when the error isn't tied to a line
+    column: usize, // 1-based, 0 when the error isn't tied to a token
+    token: String,
+    note: Option<String>, // extra help, like a suggested fix
+}
+
+impl AssembleError {
+    pub fn new(kind: ErrorKind, line: usize, column: usize, token: &str) -> Self {
+        Self {
+            kind,
+            severity: Severity::Error,
+            file: String::new(),
+            line,
+            column,
+            token: token.to_string(),
+            note: None,
+        }
+    }
+
+    // errors about the file as a whole (opening, writing...)
+    pub fn file_error(kind: ErrorKind, file: &str) -> Self {
+        Self::new(kind, 0, 0, "").in_file(file)
+    }
+
+    // same as new, but only a warning
+    pub fn warning(kind: ErrorKind, line: usize, column: usize, token: &str) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(kind, line, column, token)
+        }
+    }
+
+    // attach some extra help to the message
+    pub fn with_note(mut self, note: String) -> Self {
+        self.note = Some(note);
+        self
+    }
+
+    // attach the file name once it's known
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.file = file.to_string();
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> usize {
+        self.column
+    }
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.file.is_empty() {
+            write!(f, "{}:", self.file)?;
+        }
+        if self.line != 0 {
+            write!(f, "{}:", self.line)?;
+        }
+        if self.column != 0 {
+            write!(f, "{}:", self.column)?;
+        }
+        if !self.file.is_empty() || self.line != 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.severity, self.kind)?;
+        if !self.token.is_empty() {
+            write!(f, " '{}'", self.token)?;
+        }
+        if let Some(note) = &self.note {
+            write!(f, "\n    note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+// Every error and warning found during a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    diagnostics: Vec<AssembleError>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self {
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // both passes look at every line, so the same problem
+    // can be found twice; only keep it once
+    pub fn push(&mut self, diagnostic: AssembleError) {
+        if !self.diagnostics.contains(&diagnostic) {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    // attach the file name to every diagnostic
+    pub fn in_file(mut self, file: &str) -> Self {
+        self.diagnostics = self
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.in_file(file))
+            .collect();
+        self
+    }
+
+    // sorted by line, then column
+    pub fn diagnostics(&self) -> Vec<&AssembleError> {
+        let mut sorted: Vec<&AssembleError> = self.diagnostics.iter().collect();
+        sorted.sort_by_key(|d| (d.line(), d.column()));
+        sorted
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.count(Severity::Error)
+    }
+    pub fn warning_count(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity() == severity)
+            .count()
+    }
+}
+
+impl From<AssembleError> for Diagnostics {
+    fn from(error: AssembleError) -> Self {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(error);
+        diagnostics
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in self.diagnostics() {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        let plural = |count: usize| if count == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} error{}, {} warning{}",
+            self.error_count(),
+            plural(self.error_count()),
+            self.warning_count(),
+            plural(self.warning_count())
+        )
+    }
+}
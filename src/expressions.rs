@@ -0,0 +1,318 @@
+// Operand expressions
+// Terms are symbols, decimal numbers, hex constants like X'1F' and * (the location counter),
+// joined with + - * / and grouped with parentheses.
+// Relocatability follows the SIC rules: relative terms have to
+// cancel out in pairs, leaving at most one positive relative term.
+// External symbols count as 0, the loader adds them in from M records.
+use crate::data_records::Sign;
+use crate::errors::{AssembleError, ErrorKind};
+use crate::symbols::{Relocatability, SymbolKind, SymbolTable};
+use crate::{lookup_symbol, AssemblyLine};
+
+// The result of evaluating an expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    value: i32,
+    relative: i32, // +1 for every relative term added, -1 for every one subtracted
+    externals: Vec<(Sign, String)>, // external symbols added or subtracted
+    block: Option<usize>, // program block of the relative term, if it came from a symbol
+}
+
+impl Value {
+    pub fn absolute(value: i32) -> Self {
+        Self {
+            value,
+            relative: 0,
+            externals: Vec::new(),
+            block: None,
+        }
+    }
+
+    pub fn relative(value: i32) -> Self {
+        Self {
+            value,
+            relative: 1,
+            externals: Vec::new(),
+            block: None,
+        }
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    pub fn externals(&self) -> &Vec<(Sign, String)> {
+        &self.externals
+    }
+
+    // the program block a relative value is an address in
+    // None for * and absolute values
+    pub fn block(&self) -> Option<usize> {
+        self.block
+    }
+
+    pub fn relocatability(&self) -> Relocatability {
+        if self.relative == 0 {
+            Relocatability::Absolute
+        } else {
+            Relocatability::Relative
+        }
+    }
+}
+
+// Evaluates an operand expression
+// address_counter is the value of *
+pub fn evaluate(
+    expression: &str,
+    symtable: &SymbolTable,
+    address_counter: i32,
+    line: &AssemblyLine,
+) -> Result<Value, AssembleError> {
+    let tokens = tokenize(expression, line)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        symtable,
+        address_counter,
+        line,
+        expression,
+    };
+
+    let value = parser.expression()?;
+    if let Some(token) = parser.peek() {
+        // something left over, like a missing operator
+        return Err(line.error(ErrorKind::InvalidExpression, token));
+    }
+
+    // anything other than absolute or relative can't be loaded
+    if value.relative != 0 && value.relative != 1 {
+        return Err(line.error(ErrorKind::IllegalRelocation, expression));
+    }
+
+    Ok(value)
+}
+
+// the symbols an expression refers to
+pub fn symbols_in<'a>(expression: &'a str, line: &AssemblyLine) -> Vec<&'a str> {
+    match tokenize(expression, line) {
+        Ok(tokens) => tokens.into_iter().filter(|t| is_symbol(t)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn is_symbol(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic()) && !is_hex(token)
+}
+
+fn is_hex(token: &str) -> bool {
+    token.starts_with("X'")
+}
+
+// flips the signs of subtracted external symbols
+fn negate(externals: Vec<(Sign, String)>) -> Vec<(Sign, String)> {
+    externals
+        .into_iter()
+        .map(|(sign, name)| match sign {
+            Sign::Plus => (Sign::Minus, name),
+            Sign::Minus => (Sign::Plus, name),
+        })
+        .collect()
+}
+
+// Splits an expression into names, numbers and operators
+fn tokenize<'a>(expression: &'a str, line: &AssemblyLine) -> Result<Vec<&'a str>, AssembleError> {
+    let mut tokens = Vec::new();
+    let mut rest = expression;
+
+    while let Some(c) = rest.chars().next() {
+        let length = if let Some(hex) = rest.strip_prefix("X'") {
+            // hex constant, up to the closing quote
+            match hex.find('\'') {
+                Some(end) => end + 3,
+                None => return Err(line.error(ErrorKind::InvalidExpression, rest)),
+            }
+        } else if c.is_ascii_alphanumeric() {
+            rest.find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len())
+        } else if "+-*/()".contains(c) {
+            1
+        } else {
+            return Err(line.error(ErrorKind::InvalidExpression, &rest[..c.len_utf8()]));
+        };
+        tokens.push(&rest[..length]);
+        rest = &rest[length..];
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent over the tokens
+// expression := term (('+' | '-') term)*
+// term       := factor (('*' | '/') factor)*
+// factor     := number | hex | symbol | '*' | '-' factor | '(' expression ')'
+struct Parser<'a, 'b> {
+    tokens: Vec<&'a str>,
+    position: usize,
+    symtable: &'b SymbolTable,
+    address_counter: i32,
+    line: &'b AssemblyLine<'b>,
+    expression: &'a str,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn error(&self, kind: ErrorKind, token: &str) -> AssembleError {
+        self.line.error(kind, token)
+    }
+
+    // points at the offending token, or the whole expression if we ran out
+    fn syntax_error(&self, token: Option<&'a str>) -> AssembleError {
+        self.error(
+            ErrorKind::InvalidExpression,
+            token.unwrap_or(self.expression),
+        )
+    }
+
+    fn overflow(&self) -> AssembleError {
+        self.error(ErrorKind::InvalidExpression, self.expression)
+    }
+
+    fn expression(&mut self) -> Result<Value, AssembleError> {
+        let mut left = self.term()?;
+
+        while let Some(operator @ ("+" | "-")) = self.peek() {
+            self.next();
+            let right = self.term()?;
+            let mut externals = left.externals;
+            left = if operator == "+" {
+                externals.extend(right.externals);
+                Value {
+                    value: left
+                        .value
+                        .checked_add(right.value)
+                        .ok_or_else(|| self.overflow())?,
+                    relative: left.relative + right.relative,
+                    externals,
+                    // the block goes with whichever side is still an address
+                    block: if left.relative > 0 {
+                        left.block
+                    } else {
+                        right.block
+                    },
+                }
+            } else {
+                externals.extend(negate(right.externals));
+                Value {
+                    value: left
+                        .value
+                        .checked_sub(right.value)
+                        .ok_or_else(|| self.overflow())?,
+                    relative: left.relative - right.relative,
+                    externals,
+                    block: left.block,
+                }
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<Value, AssembleError> {
+        let mut left = self.factor()?;
+
+        while let Some(operator @ ("*" | "/")) = self.peek() {
+            self.next();
+            let right = self.factor()?;
+            // multiplying or dividing addresses means nothing to the loader
+            if left.relative != 0
+                || right.relative != 0
+                || !left.externals.is_empty()
+                || !right.externals.is_empty()
+            {
+                return Err(self.error(ErrorKind::IllegalRelocation, operator));
+            }
+            let value = if operator == "*" {
+                left.value.checked_mul(right.value)
+            } else if right.value == 0 {
+                return Err(self.error(ErrorKind::DivisionByZero, operator));
+            } else {
+                left.value.checked_div(right.value)
+            };
+            left = Value::absolute(value.ok_or_else(|| self.overflow())?);
+        }
+
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> Result<Value, AssembleError> {
+        let token = self.next();
+        match token {
+            // * where a value belongs is the location counter
+            Some("*") => Ok(Value::relative(self.address_counter)),
+            Some("-") => {
+                let value = self.factor()?;
+                Ok(Value {
+                    value: value.value.checked_neg().ok_or_else(|| self.overflow())?,
+                    relative: -value.relative,
+                    externals: negate(value.externals),
+                    block: value.block,
+                })
+            }
+            Some("(") => {
+                let value = self.expression()?;
+                match self.next() {
+                    Some(")") => Ok(value),
+                    other => Err(self.syntax_error(other)),
+                }
+            }
+            Some(hex) if is_hex(hex) => {
+                let digits = &hex[2..hex.len() - 1];
+                match i32::from_str_radix(digits, 16) {
+                    Ok(value) if !digits.starts_with(['+', '-']) => Ok(Value::absolute(value)),
+                    _ => Err(self.error(ErrorKind::InvalidExpression, hex)),
+                }
+            }
+            Some(name) if is_symbol(name) => {
+                let symbol = lookup_symbol(self.symtable, self.line, name)?;
+                // pass 2 goes through SET values in order, so before the first
+                // one there's no value yet, only the last one from pass 1
+                if symbol.kind() == SymbolKind::Set && symbol.line() > self.line.line_number() {
+                    return Err(self
+                        .error(ErrorKind::UndefinedSymbol, name)
+                        .with_note(format!("it isn't SET until line {}", symbol.line())));
+                }
+                if symbol.kind() == SymbolKind::External {
+                    return Ok(Value {
+                        value: 0,
+                        relative: 0,
+                        externals: vec![(Sign::Plus, name.to_string())],
+                        block: None,
+                    });
+                }
+                Ok(match symbol.relocatability() {
+                    Relocatability::Absolute => Value::absolute(*symbol.address()),
+                    Relocatability::Relative => Value {
+                        block: Some(symbol.block()),
+                        ..Value::relative(*symbol.address())
+                    },
+                })
+            }
+            Some(number) if number.starts_with(|c: char| c.is_ascii_digit()) => {
+                match number.parse::<i32>() {
+                    Ok(value) => Ok(Value::absolute(value)),
+                    Err(_) => Err(self.error(ErrorKind::InvalidExpression, number)),
+                }
+            }
+            other => Err(self.syntax_error(other)),
+        }
+    }
+}
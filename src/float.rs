@@ -0,0 +1,133 @@
+// SIC/XE floating point
+// A float takes up 48 bits: 1 sign bit, an 11 bit exponent and a 36 bit fraction.
+// The value is fraction * 2^(exponent - 1024), where the fraction is read as
+// a binary fraction (0.1011...) with its first bit set. Zero is all 0 bits.
+
+const FRACTION_BITS: i32 = 36;
+const EXPONENT_BIAS: i32 = 1024;
+const MAX_EXPONENT: i32 = 0x7FF;
+
+// Encodes a number into the 6 bytes of a SIC/XE float
+// the fraction gets rounded to 36 bits,
+// returns None if the number is too big or small to fit
+pub fn encode_float(value: f64) -> Option<Vec<u8>> {
+    if !value.is_finite() {
+        return None;
+    }
+    if value == 0.0 {
+        return Some(vec![0; 6]);
+    }
+
+    // get the magnitude into 0.5 <= fraction < 1
+    let mut fraction = value.abs();
+    let mut exponent = 0;
+    while fraction >= 1.0 {
+        fraction /= 2.0;
+        exponent += 1;
+    }
+    while fraction < 0.5 {
+        fraction *= 2.0;
+        exponent -= 1;
+    }
+
+    let mut fraction_bits = (fraction * 2f64.powi(FRACTION_BITS)).round() as u64;
+    // rounding up can carry all the way out of the fraction
+    if fraction_bits == 1 << FRACTION_BITS {
+        fraction_bits >>= 1;
+        exponent += 1;
+    }
+
+    let exponent = exponent + EXPONENT_BIAS;
+    if !(0..=MAX_EXPONENT).contains(&exponent) {
+        return None;
+    }
+
+    let sign = u64::from(value < 0.0);
+    let bits = (sign << 47) | ((exponent as u64) << FRACTION_BITS) | fraction_bits;
+    Some(bits.to_be_bytes()[2..].to_vec())
+}
+
+// Reads the 6 bytes of a SIC/XE float back into a number
+pub fn decode_float(bytes: &[u8]) -> f64 {
+    let bits = bytes
+        .iter()
+        .take(6)
+        .fold(0u64, |bits, byte| (bits << 8) | u64::from(*byte));
+
+    let fraction_bits = bits & ((1 << FRACTION_BITS) - 1);
+    let exponent = ((bits >> FRACTION_BITS) as i32) & MAX_EXPONENT;
+    let magnitude =
+        fraction_bits as f64 * 2f64.powi(-FRACTION_BITS) * 2f64.powi(exponent - EXPONENT_BIAS);
+
+    if bits >> 47 == 1 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the closest float is within half of the last fraction bit
+    fn assert_round_trip(value: f64) {
+        let bytes = encode_float(value).unwrap();
+        assert_eq!(bytes.len(), 6);
+        let decoded = decode_float(&bytes);
+        let tolerance = value.abs() * 2f64.powi(-FRACTION_BITS);
+        assert!(
+            (decoded - value).abs() <= tolerance,
+            "{} came back as {}",
+            value,
+            decoded
+        );
+    }
+
+    #[test]
+    fn encodes_known_values() {
+        // 1 = 0.1 (binary) * 2^1
+        assert_eq!(encode_float(1.0).unwrap(), vec![0x40, 0x18, 0, 0, 0, 0]);
+        assert_eq!(encode_float(-1.0).unwrap(), vec![0xC0, 0x18, 0, 0, 0, 0]);
+        // 0.75 = 0.11 (binary) * 2^0
+        assert_eq!(encode_float(0.75).unwrap(), vec![0x40, 0x0C, 0, 0, 0, 0]);
+        assert_eq!(encode_float(0.0).unwrap(), vec![0; 6]);
+    }
+
+    #[test]
+    fn exact_values_round_trip_exactly() {
+        for value in [0.0, 1.0, -1.0, 0.5, 0.75, 3.0, -2.5, 1024.0, 65536.125] {
+            assert_eq!(decode_float(&encode_float(value).unwrap()), value);
+        }
+    }
+
+    #[test]
+    fn inexact_values_round_trip_closely() {
+        for value in [
+            0.1,
+            -0.3,
+            std::f64::consts::PI,
+            1.0e-20,
+            6.02e23,
+            -123456.789,
+        ] {
+            assert_round_trip(value);
+        }
+    }
+
+    #[test]
+    fn rounding_carries_into_the_exponent() {
+        // just under 1, closer to 1 than to the largest 36 bit fraction
+        let value = 1.0 - 2f64.powi(-40);
+        assert_eq!(encode_float(value).unwrap(), encode_float(1.0).unwrap());
+    }
+
+    #[test]
+    fn rejects_values_that_dont_fit() {
+        assert_eq!(encode_float(f64::INFINITY), None);
+        assert_eq!(encode_float(f64::NAN), None);
+        // 2^-1030, below the smallest exponent
+        assert_eq!(encode_float(f64::MIN_POSITIVE / 256.0), None);
+        assert_eq!(encode_float(f64::MAX), None);
+    }
+}
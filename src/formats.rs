@@ -0,0 +1,48 @@
+// SIC/XE instruction formats
+// format 1: opcode (8 bits)
+// format 2: opcode (8), r1 (4), r2 (4)
+// format 3: opcode (6), n i x b p e, displacement (12)
+// format 4: opcode (6), n i x b p e, address (20)
+
+// the n i x b p e flags, in the order they sit in the instruction
+pub const N: i32 = 0b100000; // indirect
+pub const I: i32 = 0b010000; // immediate, n and i both set is simple addressing
+pub const X: i32 = 0b001000; // indexed
+pub const B: i32 = 0b000100; // base relative
+pub const P: i32 = 0b000010; // PC relative
+pub const E: i32 = 0b000001; // extended (format 4)
+
+pub fn format1(opcode: i32) -> Vec<u8> {
+    vec![opcode as u8]
+}
+
+pub fn format2(opcode: i32, r1: i32, r2: i32) -> Vec<u8> {
+    vec![opcode as u8, ((r1 << 4) | (r2 & 0xF)) as u8]
+}
+
+pub fn format3(opcode: i32, flags: i32, displacement: i32) -> Vec<u8> {
+    let word = ((opcode & 0xFC) << 16) | (flags << 12) | (displacement & 0xFFF);
+    word.to_be_bytes()[1..].to_vec()
+}
+
+pub fn format4(opcode: i32, flags: i32, address: i32) -> Vec<u8> {
+    let word = ((opcode & 0xFC) << 24) | (flags << 20) | (address & 0xFFFFF);
+    word.to_be_bytes().to_vec()
+}
+
+// Picks the displacement for a format 3 address
+// PC relative is tried first, then base relative if BASE is in effect
+// returns the b/p flag and the displacement, None if neither can reach
+pub fn displacement(target: i32, pc: i32, base: Option<i32>) -> Option<(i32, i32)> {
+    let from_pc = target.checked_sub(pc)?;
+    if (-2048..=2047).contains(&from_pc) {
+        return Some((P, from_pc));
+    }
+
+    // BASE can be any expression, so this one can overflow too
+    let from_base = target.checked_sub(base?)?;
+    if (0..=4095).contains(&from_base) {
+        return Some((B, from_base));
+    }
+    None
+}
@@ -1,18 +1,43 @@
 pub struct Instruction<'a> {
     name: &'a str,
     opcode: i32,
+    format: i32, // 1, 2 or 3 (format 3 instructions can also be written as format 4)
 }
 
 impl<'a> Instruction<'a> {
-    pub fn new(name: &'a str, opcode: i32) -> Self {
-        Instruction { name, opcode }
+    pub fn new(name: &'a str, opcode: i32, format: i32) -> Self {
+        Instruction {
+            name,
+            opcode,
+            format,
+        }
     }
 
     pub fn name(&self) -> &str {
-        &self.name
+        self.name
     }
 
     pub fn opcode(&self) -> &i32 {
         &self.opcode
     }
+
+    pub fn format(&self) -> &i32 {
+        &self.format
+    }
+}
+
+// SIC/XE register numbers, as used in format 2 instructions
+pub fn register_number(register: &str) -> Option<i32> {
+    match register {
+        "A" => Some(0),
+        "X" => Some(1),
+        "L" => Some(2),
+        "B" => Some(3),
+        "S" => Some(4),
+        "T" => Some(5),
+        "F" => Some(6),
+        "PC" => Some(8),
+        "SW" => Some(9),
+        _ => None,
+    }
 }
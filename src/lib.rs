@@ -2,440 +2,1460 @@
 */
 
 /* Big list of TODO
-* Error Handling in other places
-* * Maybe propagate it up to main.rs?
 * Records aren't being given to the ObjectData struct correctly.
 */
 
+mod blocks;
 mod data_records;
 mod directives;
+mod errors;
+mod expressions;
+mod float;
+mod formats;
 mod instructions;
+mod listing;
+mod literals;
 mod symbols;
 
-use crate::data_records::ModRecordData;
-use ascii_to_hex::ascii_to_hex;
-use data_records::ObjectData;
-use directives::is_directive;
-use instructions::Instruction;
+use blocks::BlockTable;
+pub use data_records::{ObjectData, ObjectProgram, Record, Sign};
+use directives::{is_directive, is_xe_directive};
+pub use errors::{AssembleError, Diagnostics, ErrorKind, Severity};
+use expressions::{evaluate, symbols_in, Value};
+pub use float::{decode_float, encode_float};
+use instructions::{register_number, Instruction};
+pub use listing::{Listing, ListingLine};
+use literals::{is_literal, parse_literal, Literal, LiteralTable};
 use std::{
-    env,
-    fs::{self, File},
-    io::{BufRead, BufReader, Result as ioResult, Seek, Write},
+    env, fs,
+    io::{Result as ioResult, Write},
 };
-use symbols::Symbol;
+pub use symbols::{Relocatability, Symbol, SymbolKind, SymbolTable};
 
-const MAX_MEMORY: i32 = 0x7FFF;
+const SIC_MAX_MEMORY: i32 = 0x7FFF; // 32 KB
+const XE_MAX_MEMORY: i32 = 0xFFFFF; // 1 MB
 
-// Holds inputted args (for now, just filename)
-// maybe future uses can include flags
+// a word can hold a signed 24 bit value, or an unsigned one like X'FFFFFF'
+const MIN_WORD: i32 = -0x800000;
+const MAX_WORD: i32 = 0xFFFFFF;
+
+// Holds inputted args: the filename and flags
+// sic_assembler [--target sic|xe] <filename>
 pub struct Config {
     filename: String,
+    target: Target,
 }
 
 impl Config {
-    pub fn new(mut args: env::Args) -> Result<Config, &'static str> {
+    pub fn new(mut args: env::Args) -> Result<Config, AssembleError> {
         args.next(); // discard the program itself
 
-        // get the fileName from the next argument
-        let fname = match args.next() {
-            Some(args) => args,
-            None => return Err("Usage is sic_assembler <filename>"),
-        };
+        let usage = || AssembleError::new(ErrorKind::Usage, 0, 0, "");
+        let mut fname = None;
+        let mut target = Target::Sic;
+
+        while let Some(arg) = args.next() {
+            if arg == "--target" {
+                let name = args.next().ok_or_else(usage)?;
+                target = match Target::from_name(&name) {
+                    Some(target) => target,
+                    None => return Err(AssembleError::new(ErrorKind::UnknownTarget, 0, 0, &name)),
+                };
+            } else if fname.is_none() {
+                // get the fileName from the first argument that isn't a flag
+                fname = Some(arg);
+            } else {
+                return Err(usage());
+            }
+        }
+
+        match fname {
+            Some(fname) => Ok(Config {
+                filename: fname,
+                target,
+            }),
+            None => Err(usage()),
+        }
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+}
+
+// The machine the program is assembled for
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    #[default]
+    Sic, // plain SIC, every instruction is 3 bytes
+    Xe, // SIC/XE, formats 1 to 4
+}
+
+impl Target {
+    // the name used by --target
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name {
+            "sic" => Some(Target::Sic),
+            "xe" => Some(Target::Xe),
+            _ => None,
+        }
+    }
+
+    // highest address the machine has
+    pub fn max_memory(&self) -> i32 {
+        match self {
+            Target::Sic => SIC_MAX_MEMORY,
+            Target::Xe => XE_MAX_MEMORY,
+        }
+    }
+}
 
-        Ok(Config { filename: fname })
+// Settings for assembling a program through the library
+#[derive(Default, Debug, Clone)]
+pub struct Options {
+    filename: String, // only used to label diagnostics
+    target: Target,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self {
+            filename: String::new(),
+            target: Target::Sic,
+        }
     }
 
     pub fn filename(&self) -> &str {
         &self.filename
     }
+
+    pub fn set_filename(&mut self, filename: &str) {
+        self.filename = filename.to_string();
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target;
+    }
+}
+
+// What pass 2 knows about the machine it's assembling for
+struct Machine<'a> {
+    target: Target,
+    opcodes_list: Vec<Instruction<'a>>,
+    base: Option<i32>, // what BASE said the B register holds, None after NOBASE
 }
 
 pub struct AssemblyLine<'a> {
     symbol: Option<&'a str>,
-    directive: Option<&'a str>,
+    directive: &'a str,
     operand: Option<&'a str>,
+    line_number: usize,
+    text: &'a str, // the whole line, used to find columns
 }
 
 impl<'a> AssemblyLine<'a> {
-    pub fn new(sym: Option<&'a str>, dir: Option<&'a str>, op: Option<&'a str>) -> Self {
-        AssemblyLine {
-            symbol: sym,
-            directive: dir,
-            operand: op,
-        }
+    // Tokenizes a line of source
+    // returns None for comment and blank lines
+    pub fn parse(text: &'a str, line_number: usize) -> Result<Option<Self>, AssembleError> {
+        let mut broken_line = text.split_ascii_whitespace();
+        // only symbol lines have a label
+        let symbol = match is_symbol_line(text) {
+            0 => broken_line.next(),
+            1 => None,
+            _ => return Ok(None),
+        };
+
+        let directive = match broken_line.next() {
+            Some(directive) => directive,
+            None => match symbol {
+                // a label all by itself
+                Some(symbol) => {
+                    return Err(AssembleError::new(
+                        ErrorKind::MissingInstruction,
+                        line_number,
+                        1,
+                        symbol,
+                    ))
+                }
+                // nothing but whitespace
+                None => return Ok(None),
+            },
+        };
+
+        // the operand starts after the mnemonic and can have spaces in quotes
+        let directive_end = directive.as_ptr() as usize - text.as_ptr() as usize + directive.len();
+        Ok(Some(AssemblyLine {
+            symbol,
+            directive,
+            operand: operand_field(&text[directive_end..]),
+            line_number,
+            text,
+        }))
     }
 
-    pub fn symbol(&self) -> Option<&str> {
+    pub fn symbol(&self) -> Option<&'a str> {
         self.symbol
     }
 
-    pub fn directive(&self) -> Option<&str> {
+    pub fn directive(&self) -> &'a str {
         self.directive
     }
 
-    pub fn operand(&self) -> Option<&str> {
+    pub fn operand(&self) -> Option<&'a str> {
         self.operand
     }
+
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    // 1-based column of a token taken from this line
+    // 0 for anything that isn't part of the line, like a literal ""
+    fn column(&self, token: &str) -> usize {
+        match (token.as_ptr() as usize).checked_sub(self.text.as_ptr() as usize) {
+            Some(offset) if offset <= self.text.len() => offset + 1,
+            _ => 0,
+        }
+    }
+
+    // builds an error pointing at a token on this line
+    fn error(&self, kind: ErrorKind, token: &str) -> AssembleError {
+        AssembleError::new(kind, self.line_number, self.column(token), token)
+    }
+
+    // same as error, but only a warning
+    fn warning(&self, kind: ErrorKind, token: &str) -> AssembleError {
+        AssembleError::warning(kind, self.line_number, self.column(token), token)
+    }
+
+    // the operand, or an error pointing at the mnemonic
+    fn require_operand(&self) -> Result<&'a str, AssembleError> {
+        match self.operand {
+            Some(operand) => Ok(operand),
+            None => Err(self.error(ErrorKind::MissingOperand, self.directive)),
+        }
+    }
 }
 
 // Connection between main.rs and lib.rs
-pub fn run(config: Config) -> Result<(), &'static str> {
-    let mut address_counter: i32 = 0; // address counter for symbols
-    let mut symbol_table: Vec<Symbol> = vec![]; // symbol table, initially empty.
+// Reads the file, assembles it and writes <filename>.obj
+pub fn run(config: Config) -> Result<ObjectProgram, Diagnostics> {
+    let source = fs::read_to_string(config.filename())
+        .map_err(|_| AssembleError::file_error(ErrorKind::FileRead, config.filename()))?;
+
+    let mut options = Options::new();
+    options.set_filename(config.filename());
+    options.set_target(config.target());
+
+    let program = assemble_str(&source, options)?;
+
+    write_to_file(&program, config.filename().to_string())
+        .map_err(|_| AssembleError::file_error(ErrorKind::FileWrite, config.filename()))?;
+
+    Ok(program)
+}
 
-    let sic_asm_file = File::open(&config.filename);
+// Assembles a whole program held in memory, no files involved.
+// Ok holds the program and any warnings, Err holds everything that went wrong
+pub fn assemble_str(source: &str, options: Options) -> Result<ObjectProgram, Diagnostics> {
+    let mut diagnostics = Diagnostics::new();
 
-    let sic_asm_file = match sic_asm_file {
-		    Ok(file) => file,
-	      _ => return Err("could not open file. Please ensure that file exists and that you have permission to open it."),
-	  };
+    let (object_data, symbol_tables, listing) = assemble(source, &options, &mut diagnostics);
 
-    let mut reader = BufReader::new(sic_asm_file);
-    let mut buffer = String::new();
+    let diagnostics = diagnostics.in_file(options.filename());
+    if diagnostics.has_errors() {
+        Err(diagnostics)
+    } else {
+        Ok(ObjectProgram::new(
+            object_data,
+            symbol_tables,
+            listing,
+            diagnostics,
+        ))
+    }
+}
+
+// Assembles every control section, each one gets its own H...E group
+// problems with individual lines are collected in diagnostics so we can keep going
+fn assemble(
+    source: &str,
+    options: &Options,
+    diagnostics: &mut Diagnostics,
+) -> (ObjectData, Vec<SymbolTable>, Listing) {
+    let lines: Vec<(usize, &str)> = source
+        .lines()
+        .enumerate()
+        .map(|(index, buffer)| (index + 1, buffer))
+        .collect();
+    let mut object_data = ObjectData::new();
+    let mut symbol_tables = vec![];
+    let mut listing = Listing::new();
+
+    let end_line = lines.iter().find_map(|&(line_number, buffer)| {
+        match AssemblyLine::parse(buffer, line_number) {
+            Ok(Some(line)) if line.directive() == "END" => Some(line),
+            _ => None,
+        }
+    });
+    if end_line.is_none() {
+        diagnostics.push(AssembleError::warning(
+            ErrorKind::MissingEnd,
+            lines.len(),
+            0,
+            "",
+        ));
+    }
+
+    for (number, section) in split_sections(&lines).into_iter().enumerate() {
+        let (section_data, symbol_table, section_listing, starting_address) =
+            assemble_section(section, options, diagnostics);
+        object_data.append(section_data);
+        listing.append(section_listing);
+
+        // END FIRST says where execution starts, and only the first section
+        // gets it. Modules that aren't the main program leave it out
+        let mut first_instruction = None;
+        if number == 0 {
+            match end_line.as_ref().map(|line| (line, line.operand())) {
+                Some((line, Some(operand))) => match lookup_symbol(&symbol_table, line, operand) {
+                    Ok(symbol) => first_instruction = Some(*symbol.address()),
+                    Err(err) => diagnostics.push(err),
+                },
+                Some((_, None)) => {}
+                // without END, just start at the beginning
+                None => first_instruction = starting_address,
+            }
+        }
+        write_end_record(&mut object_data, first_instruction);
+        symbol_tables.push(symbol_table);
+    }
+
+    (object_data, symbol_tables, listing)
+}
+
+// Splits the source into control sections, each CSECT starts a new one
+// anything after END stays with the last section
+fn split_sections<'a, 'b>(lines: &'b [(usize, &'a str)]) -> Vec<&'b [(usize, &'a str)]> {
+    let mut sections = vec![];
+    let mut section_start = 0;
+
+    for (index, &(line_number, buffer)) in lines.iter().enumerate() {
+        match AssemblyLine::parse(buffer, line_number) {
+            Ok(Some(line)) if line.directive() == "CSECT" && index > section_start => {
+                sections.push(&lines[section_start..index]);
+                section_start = index;
+            }
+            Ok(Some(line)) if line.directive() == "END" => break,
+            _ => {}
+        }
+    }
+
+    sections.push(&lines[section_start..]);
+    sections
+}
+
+// Runs both passes over one control section
+// returns its records (all but E), symbols, listing and starting address
+fn assemble_section(
+    lines: &[(usize, &str)],
+    options: &Options,
+    diagnostics: &mut Diagnostics,
+) -> (ObjectData, SymbolTable, Listing, Option<i32>) {
+    let mut address_counter: i32 = 0; // address counter for symbols
+    let mut max_counter: i32 = 0; // highest address reached, ORG can go back and forth
+    let mut symbol_table = SymbolTable::new(); // symbol table, initially empty.
+    let mut literal_table = LiteralTable::new(); // literals waiting for LTORG or END
+    let mut blocks = BlockTable::new(); // program blocks, from USE
+    let mut section_name = String::new(); // named by START or CSECT
+    let target = options.target();
 
     // Key is instruction, value is opcode
     let mut opcodes_list: Vec<Instruction> = vec![];
 
-    initalize_opcodes(&mut opcodes_list);
+    initalize_opcodes(&mut opcodes_list, options.target());
 
     // main loop
-    'pass1: loop {
-        buffer.clear();
-        // read a line
-        if let Ok(line) = reader.read_line(&mut buffer) {
-            // EOF Encountered
-            if line == 0 {
-                break 'pass1; // exit out of 'pass1 loop
+    'pass1: for &(line_number, buffer) in lines {
+        let line = match AssemblyLine::parse(buffer, line_number) {
+            Ok(Some(line)) => line,
+            Ok(None) => continue 'pass1, // comment line
+            Err(err) => {
+                diagnostics.push(err);
+                continue 'pass1;
             }
+        };
 
-            // memory exceeds maximum
-            if is_memory_out_of_bounds(&address_counter) {
-                return Err("Memory out of Bounds");
+        // START directive, aka first line. (CSECT for the other sections)
+        // This means that we have to set the address and move on.
+        if matches!(line.directive(), "START" | "CSECT") {
+            // address comes in as a hex string, need to convert to decimal
+            match parse_start_address(&line, target) {
+                Ok(address) => address_counter += address,
+                Err(err) => diagnostics.push(err),
             }
+            section_name = line.symbol().unwrap_or_default().to_string();
+            max_counter = address_counter;
+            continue;
+        }
+        // Add new symbol to symbol table
+        if let Err(err) = define_symbol(
+            &mut symbol_table,
+            &line,
+            address_counter,
+            &section_name,
+            blocks.current(),
+        ) {
+            diagnostics.push(err);
+        }
 
-            // check for symbol line
-            if is_symbol_line(&buffer, &mut address_counter) != 0 {
-                // not a symbol line
-                continue 'pass1;
+        // symbols from other sections, the loader fills them in
+        if line.directive() == "EXTREF" {
+            if let Err(err) =
+                define_externals(&mut symbol_table, &line, &section_name, blocks.current())
+            {
+                diagnostics.push(err);
             }
+            continue 'pass1;
+        }
 
-            // add the symbol to the symbol table
-            let mut broken_line = buffer.split_ascii_whitespace();
-            let line =
-                AssemblyLine::new(broken_line.next(), broken_line.next(), broken_line.next());
-
-            // START directive, aka first line.
-            // This means that we have to set the address and move on.
-            if line.directive().unwrap() == "START" {
-                // address comes in as a hex string, need to convert to decimal
-                let address_as_hex = i32::from_str_radix(line.operand().unwrap(), 16).unwrap();
+        // carry on counting wherever the block left off
+        if line.directive() == "USE" {
+            (address_counter, max_counter) = blocks.use_block(
+                line.operand().unwrap_or_default(),
+                address_counter,
+                max_counter,
+            );
+            continue 'pass1;
+        }
 
-                address_counter += address_as_hex;
-                continue;
+        // instructions can use a literal instead of a label
+        if let Some(operand) = line.operand() {
+            let (operand, _) = split_index(operand);
+            if !is_directive(line.directive()) && is_literal(operand) {
+                match parse_literal(operand, &line) {
+                    Ok(value) => literal_table.add(operand, value),
+                    Err(err) => diagnostics.push(err),
+                }
             }
-            // Add new symbol to symbol table
-            if let Some(symbol_name) = line.symbol() {
-                symbol_table.push(Symbol::new(symbol_name.to_string(), address_counter));
+        }
+
+        // Call function to determine address increment here
+        let address_increment = match line.directive() {
+            "ORG" => org_address(&line, &symbol_table, address_counter, max_counter, target),
+            // the literals used so far get placed right here
+            "LTORG" | "END" => {
+                let pool_end = literal_table.place_pool(address_counter, blocks.current());
+                advance_counter(&line, address_counter, pool_end - address_counter, target)
             }
+            _ => get_address_increment(&line, &symbol_table, address_counter, &opcodes_list)
+                .and_then(|increment| advance_counter(&line, address_counter, increment, target)),
+        };
 
-            // Call function to determine address increment here
-            let address_increment =
-                get_address_increment(line.directive().unwrap(), line.operand());
+        match address_increment {
+            Ok(counter) => address_counter = counter,
+            Err(err) => diagnostics.push(err),
+        }
+        max_counter = max_counter.max(address_counter);
 
-            address_counter += address_increment;
+        // nothing after END is part of the program
+        if line.directive() == "END" {
+            break 'pass1;
         }
     }
 
+    // no END, so the last pool goes at the end of the program
+    if literal_table.has_pending() {
+        address_counter = literal_table.place_pool(address_counter, blocks.current());
+        max_counter = max_counter.max(address_counter);
+    }
+
+    // now that every block's length is known, the blocks get put one after
+    // another and everything defined in them gets its real address
+    blocks.save(address_counter, max_counter);
+    max_counter = blocks.assign_addresses();
+    // every block fit on its own, but all of them together might not
+    if is_memory_out_of_bounds(&(max_counter - 1), target) {
+        let line_number = lines.last().map_or(0, |&(line_number, _)| line_number);
+        diagnostics.push(AssembleError::new(
+            ErrorKind::MemoryOutOfBounds,
+            line_number,
+            0,
+            "",
+        ));
+    }
+    for (number, block) in blocks.blocks().iter().enumerate().skip(1) {
+        symbol_table.move_block(number, block.offset());
+        literal_table.move_block(number, block.offset());
+    }
+
     // pass 2 loop: Creating the records
     let mut starting_address: Option<i32> = None; // preserve the old starting address
-    let _length = 3; // default length of object code
+    let mut end_found = false;
     let mut object_data = ObjectData::new();
-    let mut mod_records: Vec<ModRecordData> = vec![];
-
-    reader.rewind().unwrap(); // reset the reader
+    let mut mod_records: Vec<Record> = vec![];
+    let mut link_records: Vec<Record> = vec![]; // D and R records, they go after the header
+    let mut listing = Listing::new();
+    let mut current_pool: usize = 0; // literal pool placed by the next LTORG or END
+    let mut machine = Machine {
+        target,
+        opcodes_list,
+        base: None,
+    };
+    // pass 1 has already found where the program ends
+    let program_end = max_counter;
+    address_counter = 0;
+    max_counter = 0;
+    blocks.reset();
 
-    'pass2: loop {
-        // empty out the buffer
-        buffer.clear();
-        if let Ok(line) = reader.read_line(&mut buffer) {
-            if line == 0 {
-                // hit EOF
-                break 'pass2;
+    'pass2: for &(line_number, buffer) in lines {
+        // anything wrong with the line itself was reported in pass 1
+        let line = match AssemblyLine::parse(buffer, line_number) {
+            Ok(Some(line)) => line,
+            _ => {
+                listing.add_line(Some(line_number), None, buffer, vec![]);
+                continue 'pass2;
             }
+        };
 
-            match is_symbol_line(&buffer, &mut address_counter) {
-                0 => { // symbol line
-                     // just fall through to the symbol
-                }
-                1 => {
-                    // non-symbol assembly line
-                    // tokenize the line
-                    let mut broken_line = buffer.split_ascii_whitespace();
-                    let line = AssemblyLine::new(None, broken_line.next(), broken_line.next());
-
-                    // Need to write textRecords here
-                    write_text_record(
-                        &mut object_data,
-                        &symbol_table,
-                        line.directive().unwrap(),
-                        line.operand(),
-                    );
-
-                    if !is_directive(line.directive().unwrap())
-                        && line.directive().unwrap() != "RSUB"
-                    {
-                        add_mod_record(
-                            &mut mod_records,
-                            &starting_address.unwrap(),
-                            &4,
-                            line.symbol(),
-                        );
-                    }
-                    let increment =
-                        get_address_increment(line.directive().unwrap(), line.operand());
-
-                    address_counter += increment;
-                    continue 'pass2;
-                }
-                _ => {
-                    // comment line
-                    continue 'pass2;
+        if end_found {
+            diagnostics.push(line.warning(ErrorKind::AfterEnd, line.directive()));
+            listing.add_line(Some(line_number), None, buffer, vec![]);
+            continue 'pass2;
+        }
+
+        // keep track of where each symbol gets used
+        if let Some(operand) = line.operand() {
+            // commas split lists (EXTDEF A,B) and index registers (BUFFER,X)
+            for term in operand.trim_start_matches(['#', '@']).split(',') {
+                for name in symbols_in(term, &line) {
+                    symbol_table.add_reference(name, line.line_number());
                 }
             }
+        }
 
-            // tokenize the line containing a symbol
-            let mut broken_line = buffer.split_ascii_whitespace();
-            let line =
-                AssemblyLine::new(broken_line.next(), broken_line.next(), broken_line.next());
-
-            let increment = get_address_increment(line.directive().unwrap(), line.operand());
-
-            address_counter += increment;
-
-            // START and END handling
-            if line.directive().unwrap() == "START" {
-                // starting_address has not been set yet, meaning
-                // this is the first (and only valid) call of START
-                if let None = starting_address {
-                    starting_address = Some(
-                        // Set starting_address
-                        i32::from_str_radix(
-                            line.operand()
-                                .expect("ERROR: Starting address not included."),
-                            16,
-                        )
-                        .expect("ERROR: Starting address is not a valid hex number!"),
-                    );
-                    // Define an ending address
-                    let end_address = address_counter - starting_address.unwrap();
-                    // write the head record
-                    write_head_record(
-                        &mut object_data,
-                        line.symbol().expect("ERROR: No program name included."),
-                        &end_address,
-                        starting_address.unwrap(),
-                    );
-                } else {
-                    // starting address has been defined already, which means START has been called
-                    // twice!
-                    return Err("ERROR: Starting address was already defined!\n Maybe you called START twice?");
-                }
-            // The first (and only valid call) of END
-            } else if line.directive().unwrap() == "END" {
-                // In theory, END comes after START
-                if let Some(start_address) = starting_address {
-                    write_end_record(&mut object_data, &start_address);
-                    write_mod_record(&mut object_data, &mut mod_records);
-                    // write to file
-                    match write_to_file(&object_data, config.filename().to_string()) {
-                        Ok(_) => {
-                            break;
-                        }
-                        Err(_) => return Err("Error writing to file."),
-                    }
-                } else {
-                    return Err(
-                        // but sometimes humans err, and that's why we handle such cases
-                        "ERROR: Starting Address not assigned.\n Maybe you didn't use START?",
-                    );
+        // address of this line's object code, START sets its own
+        let line_address = if matches!(line.directive(), "START" | "CSECT") {
+            parse_start_address(&line, target).unwrap_or(0)
+        } else {
+            address_counter
+        };
+        // EQU and SET lines list the value they give their symbol instead
+        let symbol_value = match line.directive() {
+            "EQU" | "SET" => parse_symbol_value(&symbol_table, &line, line_address).ok(),
+            _ => None,
+        };
+        let listed_address = symbol_value.as_ref().map_or(line_address, Value::value);
+        listing.add_line(Some(line_number), Some(listed_address), buffer, vec![]);
+
+        let next_counter = if line.directive() == "ORG" {
+            org_address(&line, &symbol_table, address_counter, max_counter, target)
+        } else {
+            get_address_increment(&line, &symbol_table, address_counter, &machine.opcodes_list)
+                .and_then(|inc| advance_counter(&line, address_counter, inc, target))
+        };
+        if let Ok(counter) = next_counter {
+            address_counter = counter;
+        }
+        max_counter = max_counter.max(address_counter);
+
+        // SET symbols take on each new value as pass 2 reaches it
+        if line.directive() == "SET" {
+            if let (Some(name), Some(value)) = (line.symbol(), symbol_value) {
+                if let Some(symbol) = symbol_table.get_mut(name) {
+                    symbol.set_address(value.value(), value.relocatability());
                 }
             }
+            continue 'pass2;
+        }
 
-            // write text records
-            write_text_record(
+        if line.directive() == "LTORG" {
+            address_counter = write_literal_pool(
                 &mut object_data,
-                &symbol_table,
-                line.directive().unwrap(),
-                line.operand(),
+                &mut listing,
+                &literal_table.pool(current_pool),
+                address_counter,
+            );
+            current_pool += 1;
+            max_counter = max_counter.max(address_counter);
+            continue 'pass2;
+        }
+
+        // some directives only make sense on SIC/XE
+        if target == Target::Sic && is_xe_directive(line.directive()) {
+            diagnostics.push(line.error(ErrorKind::RequiresXe, line.directive()));
+            continue 'pass2;
+        }
+
+        if line.directive() == "USE" {
+            (address_counter, max_counter) = blocks.use_block(
+                line.operand().unwrap_or_default(),
+                address_counter,
+                max_counter,
             );
+            continue 'pass2;
+        }
+
+        // symbols this section shares with others (D records)
+        // and the ones it uses from them (R records)
+        if matches!(line.directive(), "EXTDEF" | "EXPORTS") {
+            if let Err(err) = write_define_record(&mut link_records, &mut symbol_table, &line) {
+                diagnostics.push(err);
+            }
+            continue 'pass2;
+        } else if line.directive() == "EXTREF" {
+            write_refer_record(&mut link_records, &line);
+            continue 'pass2;
+        }
+
+        // BASE tells us what the B register will hold when the program runs
+        if line.directive() == "BASE" {
+            match line
+                .require_operand()
+                .and_then(|operand| evaluate(operand, &symbol_table, line_address, &line))
+            {
+                Ok(value) => machine.base = Some(value.value()),
+                Err(err) => diagnostics.push(err),
+            }
+            continue 'pass2;
+        } else if line.directive() == "NOBASE" {
+            machine.base = None;
+            continue 'pass2;
         }
+
+        // START (or CSECT) and END handling
+        if matches!(line.directive(), "START" | "CSECT") {
+            // starting_address has not been set yet, meaning
+            // this is the first (and only valid) call of START
+            if starting_address.is_some() {
+                // starting address has been defined already, which means START has been called
+                // twice!
+                diagnostics.push(line.error(ErrorKind::DuplicateStart, line.directive()));
+                continue 'pass2;
+            }
+            let program_name = match parse_program_name(&line) {
+                Ok(name) => name,
+                Err(err) => {
+                    diagnostics.push(err);
+                    ""
+                }
+            };
+            // Set starting_address
+            let start_address = parse_start_address(&line, target).unwrap_or(0);
+            starting_address = Some(start_address);
+            address_counter = start_address;
+            // pass 1's final location counter gives the length
+            let program_length = program_end - start_address;
+            // write the head record
+            write_head_record(
+                &mut object_data,
+                program_name,
+                start_address,
+                program_length,
+            );
+            continue 'pass2;
+        // The first (and only valid call) of END
+        } else if line.directive() == "END" {
+            end_found = true;
+            // In theory, END comes after START
+            if starting_address.is_none() {
+                // but sometimes humans err, and that's why we handle such cases
+                diagnostics.push(line.error(ErrorKind::MissingStart, line.directive()));
+            }
+            // the last literal pool goes after the program
+            write_literal_pool(
+                &mut object_data,
+                &mut listing,
+                &literal_table.pool(current_pool),
+                address_counter,
+            );
+            continue 'pass2;
+        }
+
+        // write text records
+        match write_text_record(
+            &mut object_data,
+            &mut mod_records,
+            &symbol_table,
+            &literal_table.pool(current_pool),
+            &machine,
+            &line,
+            line_address,
+        ) {
+            Ok(object_code) => listing.set_object_code(object_code),
+            Err(err) => diagnostics.push(err),
+        }
+    }
+
+    // the section ends without END, so its literals go at the end of it
+    if !end_found {
+        write_literal_pool(
+            &mut object_data,
+            &mut listing,
+            &literal_table.pool(current_pool),
+            address_counter,
+        );
     }
 
+    // END catches a missing START, but without END nothing has yet
+    if !end_found && starting_address.is_none() {
+        let line_number = lines.first().map_or(0, |&(line_number, _)| line_number);
+        diagnostics.push(AssembleError::new(
+            ErrorKind::MissingStart,
+            line_number,
+            0,
+            "",
+        ));
+    }
+
+    write_link_records(&mut object_data, &mut link_records);
+    write_mod_record(&mut object_data, &mut mod_records);
+
+    (object_data, symbol_table, listing, starting_address)
+}
+
+// reads the hex operand of START
+// control sections are relocated on their own, so CSECT always starts at 0
+fn parse_start_address(line: &AssemblyLine, target: Target) -> Result<i32, AssembleError> {
+    if line.directive() == "CSECT" {
+        return Ok(0);
+    }
+    let operand = line.require_operand()?;
+    match i32::from_str_radix(operand, 16) {
+        Ok(address) if !is_memory_out_of_bounds(&address, target) && address >= 0 => Ok(address),
+        Ok(_) => Err(line.error(ErrorKind::MemoryOutOfBounds, operand)),
+        Err(_) => Err(line.error(ErrorKind::InvalidOperand, operand)),
+    }
+}
+
+// Adds the label of a line to the symbol table
+// Labels can only be defined once, unless every definition uses SET
+fn define_symbol(
+    symtable: &mut SymbolTable,
+    line: &AssemblyLine,
+    address_counter: i32,
+    section: &str,
+    block: usize,
+) -> Result<(), AssembleError> {
+    let kind = match line.directive() {
+        "SET" => SymbolKind::Set,
+        "EQU" => SymbolKind::Equ,
+        _ => SymbolKind::Label,
+    };
+    let name = match line.symbol() {
+        Some(name) => name,
+        // EQU and SET are useless without a name
+        None if kind != SymbolKind::Label => {
+            return Err(line.error(ErrorKind::MissingLabel, line.directive()))
+        }
+        None => return Ok(()),
+    };
+
+    // labels are addresses in the program, so they move with it
+    // EQU BUF+3 belongs to BUF's block, which might not be the one in use
+    let (value, relocatability, block) = match kind {
+        SymbolKind::Label => (address_counter, Relocatability::Relative, block),
+        _ => {
+            let value = parse_symbol_value(symtable, line, address_counter)?;
+            (
+                value.value(),
+                value.relocatability(),
+                value.block().unwrap_or(block),
+            )
+        }
+    };
+    let is_set = kind == SymbolKind::Set;
+
+    match symtable.get_mut(name) {
+        // SET can keep on changing a SET symbol
+        Some(symbol) if is_set && symbol.redefinable() => {
+            symbol.set_address(value, relocatability);
+            symbol.set_block(block);
+        }
+        Some(symbol) => {
+            return Err(line
+                .error(ErrorKind::DuplicateSymbol, name)
+                .with_note(format!(
+                    "'{}' was first defined on line {}",
+                    name,
+                    symbol.line()
+                )));
+        }
+        None => symtable.insert(Symbol::new(
+            name.to_string(),
+            value,
+            kind,
+            relocatability,
+            line.line_number(),
+            section.to_string(),
+            block,
+        )),
+    }
+
+    Ok(())
+}
+
+// Value given to an EQU or SET symbol: an expression
+// using only symbols that are already defined
+fn parse_symbol_value(
+    symtable: &SymbolTable,
+    line: &AssemblyLine,
+    address_counter: i32,
+) -> Result<Value, AssembleError> {
+    let operand = line.require_operand()?;
+    let value = evaluate(operand, symtable, address_counter, line)?;
+    // the value of an external symbol isn't known until load time
+    if !value.externals().is_empty() {
+        return Err(line.error(ErrorKind::ExternalReference, operand));
+    }
+    Ok(value)
+}
+
+// Adds the symbols named by EXTREF to the symbol table
+fn define_externals(
+    symtable: &mut SymbolTable,
+    line: &AssemblyLine,
+    section: &str,
+    block: usize,
+) -> Result<(), AssembleError> {
+    for name in line.require_operand()?.split(',') {
+        if let Some(symbol) = symtable.get(name) {
+            return Err(line
+                .error(ErrorKind::DuplicateSymbol, name)
+                .with_note(format!(
+                    "'{}' was first defined on line {}",
+                    name,
+                    symbol.line()
+                )));
+        }
+        symtable.insert(Symbol::new(
+            name.to_string(),
+            0,
+            SymbolKind::External,
+            Relocatability::Absolute,
+            line.line_number(),
+            section.to_string(),
+            block,
+        ));
+    }
     Ok(())
 }
 
-// checks if memory is out of bounds (SIC Max memory is 0x0000 to 0x7FFF)
-fn is_memory_out_of_bounds(current_counter: &i32) -> bool {
-    if *current_counter >= MAX_MEMORY {
-        return true;
+// the label of START names the program, it has to fit in
+// the 6 columns of the head record
+fn parse_program_name<'a>(line: &AssemblyLine<'a>) -> Result<&'a str, AssembleError> {
+    let name = match line.symbol() {
+        Some(name) => name,
+        None => return Err(line.error(ErrorKind::MissingProgramName, line.directive())),
+    };
+
+    if name.len() > 6 || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(line.error(ErrorKind::InvalidProgramName, name));
+    }
+
+    Ok(name)
+}
+
+// Where ORG moves the address counter to
+// ORG with no operand goes back to the highest address used so far
+fn org_address(
+    line: &AssemblyLine,
+    symtable: &SymbolTable,
+    address_counter: i32,
+    max_counter: i32,
+    target: Target,
+) -> Result<i32, AssembleError> {
+    let operand = match line.operand() {
+        Some(operand) => operand,
+        None => return Ok(max_counter),
+    };
+
+    let address = evaluate(operand, symtable, address_counter, line)?.value();
+    // one past the end is fine, as long as nothing else gets put there
+    if address < 0 || is_memory_out_of_bounds(&(address - 1), target) {
+        return Err(line.error(ErrorKind::MemoryOutOfBounds, operand));
+    }
+
+    Ok(address)
+}
+
+// moves the address counter forward, making sure we stay in memory
+fn advance_counter(
+    line: &AssemblyLine,
+    counter: i32,
+    increment: i32,
+    target: Target,
+) -> Result<i32, AssembleError> {
+    match counter.checked_add(increment) {
+        // the counter may sit one past the last byte we used
+        Some(next) if increment == 0 || !is_memory_out_of_bounds(&(next - 1), target) => Ok(next),
+        _ => Err(line.error(ErrorKind::MemoryOutOfBounds, line.directive())),
     }
+}
 
-    false
+// checks if memory is out of bounds
+// (SIC Max memory is 0x0000 to 0x7FFF, SIC/XE goes up to 0xFFFFF)
+fn is_memory_out_of_bounds(current_counter: &i32, target: Target) -> bool {
+    *current_counter > target.max_memory()
 }
 
 // Creates text records
-// arguments: records holder, symbol table,
-// opcodes, directive for the line, operand for the line
-// length of the record, starting address, and text index
-// Some of these may be unnecessary with Rust
+// arguments: records holder, modification records, symbol table,
+// the literal pool in use, the machine, the line being assembled and its address
+// Instructions are assembled for the target machine,
+// directives only produce data for WORD and BYTE
+// returns the object code of the line
 fn write_text_record(
     object_data: &mut ObjectData,
-    symtable: &Vec<Symbol>,
-    directive: &str,
-    operand: Option<&str>,
-) {
-    // this function wil return text_data
-    let mut text_data: String = String::new();
-    let mut symbol_address = 0;
-
-    // The operand does not exit only when the directive is RSUB
-    if let Some(operand) = operand {
-        // need to locate the symbol in the symbol table
-        // as well as the instruction
-        let symbol = find_symbol(symtable, operand);
-        let opcode = find_instruction(directive, operand);
-
-        // If we didn't find the symbol, we need to error
-        if let Some(symbol) = symbol {
-            // we found the symbol
-            // need to set the symbol address
-            symbol_address = *symbol.address();
-        }
-
-        if let Some(instruction) = opcode {
-            if !is_directive(directive) {
-                // instruction, so the object code is OP and ADDR
-                text_data = format!("{:02X}{:04X}", instruction.opcode(), symbol_address);
-            } else {
-                // It's just a directive
-                // BYTE directives can exceed the 60 character object code limit
-                // Please look forward to it (tm)
-                if operand.len() > 60 {
-                    todo!(); // please do look forward to it (tm)
-                } else {
-                    if instruction.name() == "WORD" {
-                        // word format is %06X
-                        text_data = format!("{:06X}", instruction.opcode());
-                    } else {
-                        // This was uncommented in C Code
-                        // Dangit past me
-                        text_data = format!("{}", operand);
-                    }
-                }
+    mod_records: &mut Vec<Record>,
+    symtable: &SymbolTable,
+    literal_pool: &[&Literal],
+    machine: &Machine,
+    line: &AssemblyLine,
+    line_address: i32,
+) -> Result<Vec<u8>, AssembleError> {
+    let directive = line.directive();
+    // this function wil return the object code
+    let object_code: Vec<u8>;
+
+    if !is_directive(directive) {
+        object_code = match machine.target {
+            Target::Sic => assemble_sic_instruction(
+                mod_records,
+                symtable,
+                literal_pool,
+                &machine.opcodes_list,
+                line,
+                line_address,
+            )?,
+            Target::Xe => assemble_xe_instruction(
+                mod_records,
+                symtable,
+                literal_pool,
+                machine,
+                line,
+                line_address,
+            )?,
+        };
+    } else if directive == "WORD" {
+        // one word for each value in the list, like WORD 1,2,3
+        let list = line.require_operand()?;
+        let mut words = vec![];
+        for (index, operand) in split_operands(list).into_iter().enumerate() {
+            // a stray comma, like WORD 1,
+            if operand.is_empty() {
+                return Err(line.error(ErrorKind::InvalidOperand, list));
+            }
+            let word_address = line_address + 3 * index as i32;
+            let value = evaluate(operand, symtable, word_address, line)?;
+            if !(MIN_WORD..=MAX_WORD).contains(&value.value()) {
+                return Err(line.error(ErrorKind::WordOutOfRange, operand));
+            }
+            // a word holding an address gets relocated as a whole
+            if value.relocatability() == Relocatability::Relative {
+                add_mod_record(mod_records, &word_address, &6, None);
+            }
+            for external in value.externals() {
+                add_mod_record(mod_records, &word_address, &6, Some(external));
+            }
+            // word format is %06X, negative values in two's complement
+            words.extend(word_to_bytes(value.value()));
+        }
+        object_code = words;
+    } else if directive == "REAL" {
+        // floating point constant, written as a decimal number
+        // (not FLOAT, that's the SIC/XE instruction)
+        let operand = line.require_operand()?;
+        object_code = match operand.parse::<f64>().ok().and_then(encode_float) {
+            Some(bytes) => bytes,
+            None => return Err(line.error(ErrorKind::InvalidOperand, operand)),
+        };
+    } else if directive == "BYTE" {
+        // add_text splits long constants across as many records as it takes
+        object_code = byte_constants(line)?;
+    } else {
+        // no object code for this directive (RESB, RESW, END...)
+        return Ok(vec![]);
+    }
+
+    object_data.add_text(line_address, &object_code);
+    Ok(object_code)
+}
+
+// Assembles a SIC instruction, object code is OP and ADDR
+fn assemble_sic_instruction(
+    mod_records: &mut Vec<Record>,
+    symtable: &SymbolTable,
+    literal_pool: &[&Literal],
+    opcodes_list: &[Instruction],
+    line: &AssemblyLine,
+    line_address: i32,
+) -> Result<Vec<u8>, AssembleError> {
+    let directive = line.directive();
+    // format 4 is SIC/XE only
+    if directive.starts_with('+') {
+        return Err(line.error(ErrorKind::RequiresXe, directive));
+    }
+    let instruction = match find_opcode(opcodes_list, directive) {
+        Some(instruction) => instruction,
+        None => return Err(line.error(ErrorKind::UnknownInstruction, directive)),
+    };
+
+    // The operand does not exist only when the instruction is RSUB
+    let mut target_address = 0;
+    let mut x_bit = 0;
+    if directive != "RSUB" {
+        let operand = line.require_operand()?;
+        // and so are immediate and indirect addressing
+        if operand.starts_with(['#', '@']) {
+            return Err(line.error(ErrorKind::RequiresXe, operand));
+        }
+        let (operand, register) = split_index(operand);
+        match register {
+            Some("X") => x_bit = 0x8000,
+            Some(register) => return Err(line.error(ErrorKind::InvalidIndexRegister, register)),
+            None => {}
+        }
+        let target = resolve_operand(operand, symtable, literal_pool, line_address, line)?;
+        if target.value() < 0 || is_memory_out_of_bounds(&target.value(), Target::Sic) {
+            return Err(line.error(ErrorKind::MemoryOutOfBounds, operand));
+        }
+        target_address = target.value();
+
+        // the loader has to relocate address fields that point into the program,
+        // the field starts one byte into the instruction
+        if target.relocatability() == Relocatability::Relative {
+            add_mod_record(mod_records, &(line_address + 1), &4, None);
+        }
+    }
+
+    // SIC instruction word: 8 bit opcode, 1 bit index flag, 15 bit address
+    Ok(word_to_bytes(
+        (instruction.opcode() << 16) | x_bit | target_address,
+    ))
+}
+
+// Assembles a SIC/XE instruction in whichever format it uses
+// +MNEMONIC is format 4, operands can start with # (immediate) or @ (indirect)
+fn assemble_xe_instruction(
+    mod_records: &mut Vec<Record>,
+    symtable: &SymbolTable,
+    literal_pool: &[&Literal],
+    machine: &Machine,
+    line: &AssemblyLine,
+    line_address: i32,
+) -> Result<Vec<u8>, AssembleError> {
+    let directive = line.directive();
+    let (mnemonic, extended) = match directive.strip_prefix('+') {
+        Some(mnemonic) => (mnemonic, true),
+        None => (directive, false),
+    };
+    let instruction = match find_opcode(&machine.opcodes_list, mnemonic) {
+        Some(instruction) => instruction,
+        None => return Err(line.error(ErrorKind::UnknownInstruction, directive)),
+    };
+    let opcode = *instruction.opcode();
+
+    match instruction.format() {
+        _ if extended && *instruction.format() != 3 => {
+            return Err(line.error(ErrorKind::InvalidFormat, directive))
+        }
+        1 => return Ok(formats::format1(opcode)),
+        2 => {
+            let (r1, r2) = format2_operands(line, mnemonic)?;
+            return Ok(formats::format2(opcode, r1, r2));
+        }
+        _ => {}
+    }
+
+    // RSUB has nothing to address
+    if mnemonic == "RSUB" {
+        return Ok(if extended {
+            formats::format4(opcode, formats::N | formats::I | formats::E, 0)
+        } else {
+            formats::format3(opcode, formats::N | formats::I, 0)
+        });
+    }
+
+    let operand = line.require_operand()?;
+    let (mut flags, operand) = match operand.strip_prefix('#') {
+        Some(operand) => (formats::I, operand),
+        None => match operand.strip_prefix('@') {
+            Some(operand) => (formats::N, operand),
+            None => (formats::N | formats::I, operand),
+        },
+    };
+    let (operand, register) = split_index(operand);
+    match register {
+        // indexing only goes with simple addressing
+        Some("X") if flags != formats::N | formats::I => {
+            return Err(line.error(ErrorKind::InvalidOperand, line.require_operand()?))
+        }
+        Some("X") => flags |= formats::X,
+        Some(register) => return Err(line.error(ErrorKind::InvalidIndexRegister, register)),
+        None => {}
+    }
+
+    let target = resolve_operand(operand, symtable, literal_pool, line_address, line)?;
+    let relative = target.relocatability() == Relocatability::Relative;
+    // external addresses only fit in format 4
+    if !extended && !target.externals().is_empty() {
+        return Err(line
+            .error(ErrorKind::ExternalReference, operand)
+            .with_note("use format 4 (+)".to_string()));
+    }
+
+    // format 4 holds the whole address, so the loader has to fix it up
+    if extended {
+        if target.value() < 0 || is_memory_out_of_bounds(&target.value(), machine.target) {
+            return Err(line.error(ErrorKind::MemoryOutOfBounds, operand));
+        }
+        if relative {
+            add_mod_record(mod_records, &(line_address + 1), &5, None);
+        }
+        for external in target.externals() {
+            add_mod_record(mod_records, &(line_address + 1), &5, Some(external));
+        }
+        return Ok(formats::format4(opcode, flags | formats::E, target.value()));
+    }
+
+    // constants and small absolute addresses go straight into the displacement
+    if !relative {
+        if (0..=4095).contains(&target.value()) {
+            return Ok(formats::format3(opcode, flags, target.value()));
+        }
+        return Err(line
+            .error(ErrorKind::DisplacementOutOfRange, operand)
+            .with_note("use format 4 (+) for values over 4095".to_string()));
+    }
+
+    // addresses in the program are reached from the PC, or from BASE
+    if target.value() < 0 || is_memory_out_of_bounds(&target.value(), machine.target) {
+        return Err(line.error(ErrorKind::MemoryOutOfBounds, operand));
+    }
+    match formats::displacement(target.value(), line_address + 3, machine.base) {
+        Some((mode, displacement)) => Ok(formats::format3(opcode, flags | mode, displacement)),
+        None => Err(line
+            .error(ErrorKind::DisplacementOutOfRange, operand)
+            .with_note("use format 4 (+) or set BASE closer to it".to_string())),
+    }
+}
+
+// The registers (or count) of a format 2 instruction
+// like CLEAR A, COMPR A,S, SHIFTL T,4 or SVC 2
+fn format2_operands(line: &AssemblyLine, mnemonic: &str) -> Result<(i32, i32), AssembleError> {
+    let operand = line.require_operand()?;
+    let (first, second) = match operand.split_once(',') {
+        Some((first, second)) => (first, Some(second)),
+        None => (operand, None),
+    };
+
+    let register = |name: &str| {
+        register_number(name).ok_or_else(|| line.error(ErrorKind::UnknownRegister, name))
+    };
+    // SVC keeps its number as is, shifts store one less than the count
+    let count = |number: &str, offset: i32| match number.parse::<i32>() {
+        Ok(count) if (offset..=15 + offset).contains(&count) => Ok(count - offset),
+        _ => Err(line.error(ErrorKind::InvalidOperand, number)),
+    };
+
+    match (mnemonic, second) {
+        ("SVC", None) => Ok((count(first, 0)?, 0)),
+        ("SHIFTL" | "SHIFTR", Some(second)) => Ok((register(first)?, count(second, 1)?)),
+        ("CLEAR" | "TIXR", None) => Ok((register(first)?, 0)),
+        ("SVC" | "SHIFTL" | "SHIFTR" | "CLEAR" | "TIXR", _) => {
+            Err(line.error(ErrorKind::InvalidOperand, operand))
+        }
+        (_, Some(second)) => Ok((register(first)?, register(second)?)),
+        (_, None) => Err(line.error(ErrorKind::InvalidOperand, operand)),
+    }
+}
+
+// Splits the index register off an operand, BUFFER,X gives BUFFER and X
+// a comma inside a literal like =C'A,B' isn't one
+fn split_index(operand: &str) -> (&str, Option<&str>) {
+    let operands = split_operands(operand);
+    match operands[..] {
+        [_] => (operand, None),
+        [.., register] => (
+            &operand[..operand.len() - register.len() - 1],
+            Some(register),
+        ),
+        [] => (operand, None),
+    }
+}
+
+// The value of an instruction operand
+// a literal stands for the address it was placed at, like a label would
+fn resolve_operand(
+    operand: &str,
+    symtable: &SymbolTable,
+    literal_pool: &[&Literal],
+    address_counter: i32,
+    line: &AssemblyLine,
+) -> Result<Value, AssembleError> {
+    if !is_literal(operand) {
+        return evaluate(operand, symtable, address_counter, line);
+    }
+
+    let value = parse_literal(operand, line)?;
+    match literal_pool
+        .iter()
+        .find(|literal| *literal.value() == value)
+        .and_then(|literal| literal.address())
+    {
+        Some(address) => Ok(Value::relative(address)),
+        None => Err(line.error(ErrorKind::InvalidOperand, operand)),
+    }
+}
+
+// Writes the literals of a pool as data, each one gets a line in the listing
+// returns the address counter after the pool
+fn write_literal_pool(
+    object_data: &mut ObjectData,
+    listing: &mut Listing,
+    literal_pool: &[&Literal],
+    address_counter: i32,
+) -> i32 {
+    let mut address_counter = address_counter;
+    for literal in literal_pool {
+        let address = literal.address().unwrap_or(address_counter);
+        object_data.add_text(address, literal.value());
+        listing.add_line(
+            None,
+            Some(address),
+            &format!("*\t{}", literal.name()),
+            literal.value().clone(),
+        );
+        address_counter = address + literal.value().len() as i32;
+    }
+    address_counter
+}
+
+// The first word of the rest of a line, where a quoted
+// part like C'HI THERE' counts as one word
+fn operand_field(rest: &str) -> Option<&str> {
+    let rest = rest.trim_start();
+    let mut quoted = false;
+    let end = rest
+        .find(|c: char| {
+            if c == '\'' {
+                quoted = !quoted;
+            }
+            c.is_ascii_whitespace() && !quoted
+        })
+        .unwrap_or(rest.len());
+    if end == 0 {
+        None
+    } else {
+        Some(&rest[..end])
+    }
+}
+
+// Splits a list of operands at the commas,
+// leaving commas inside quotes (C'A,B') alone
+fn split_operands(operand: &str) -> Vec<&str> {
+    let mut operands = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    for (index, c) in operand.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                operands.push(&operand[start..index]);
+                start = index + 1;
             }
+            _ => {}
         }
     }
-    object_data.add_text_records(format!("T{}\n", text_data));
+    operands.push(&operand[start..]);
+    operands
+}
+
+// splits a 24 bit SIC word into its 3 bytes
+fn word_to_bytes(word: i32) -> Vec<u8> {
+    word.to_be_bytes()[1..].to_vec()
 }
 
 // writes head record
 fn write_head_record(
     object_data: &mut ObjectData,
-    start_symbol: &str,
-    start_address: &i32,
+    program_name: &str,
+    start_address: i32,
     length: i32,
 ) {
-    object_data.set_head_record(format!(
-        "H{}{:06X}{:06X}\n",
-        start_symbol, start_address, length
-    ));
+    object_data.add_record(Record::Header {
+        name: program_name.to_string(),
+        start_address,
+        length,
+    });
+}
+
+// Writes a define record for the symbols named by EXTDEF or EXPORTS
+// and marks them as exported
+fn write_define_record(
+    link_records: &mut Vec<Record>,
+    symtable: &mut SymbolTable,
+    line: &AssemblyLine,
+) -> Result<(), AssembleError> {
+    let mut symbols = vec![];
+    for name in line.require_operand()?.split(',') {
+        let symbol = lookup_symbol(symtable, line, name)?;
+        symbols.push((name.to_string(), *symbol.address()));
+    }
+    for (name, _) in &symbols {
+        if let Some(symbol) = symtable.get_mut(name) {
+            symbol.export();
+        }
+    }
+    link_records.push(Record::Define { symbols });
+    Ok(())
+}
+
+// Writes a refer record for the symbols named by EXTREF
+fn write_refer_record(link_records: &mut Vec<Record>, line: &AssemblyLine) {
+    let symbols = line
+        .operand()
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.to_string())
+        .collect();
+    link_records.push(Record::Refer { symbols });
 }
 
 // Writes end record
-fn write_end_record(object_data: &mut ObjectData, start_address: &i32) {
-    object_data.set_end_record(format!("E{:06X}\n", start_address));
+fn write_end_record(object_data: &mut ObjectData, first_instruction: Option<i32>) {
+    object_data.add_record(Record::End { first_instruction });
 }
 
 /*
 * Writes the modification records
-* If I recall correctly, modification records
-* happen on every instruction that isn't RSUB.
-* At least, that's what I can gather from my
-* poorly commented C code.
-* I forget the reasoning why, but there's two mod record functions
-* I may be able to condense them down with Rust powers.
+* Modification records happen on every address field that
+* holds a relative value (a label, *, BUFFER+3...), since that
+* value changes when the loader moves the program.
+* Absolute operands (constants, EQU values, RSUB) are left alone.
+* External symbols get one each, saying which symbol to add or subtract.
+* They're collected during pass 2 and written after the text records.
 */
 // This takes a collection of records, and fills them out
+// address is the first byte to modify, mod_length is in half bytes
 fn add_mod_record(
-    mod_records: &mut Vec<ModRecordData>,
-    starting_address: &i32,
+    mod_records: &mut Vec<Record>,
+    address: &i32,
     mod_length: &i32,
-    symbol: Option<&str>,
+    symbol: Option<&(Sign, String)>,
 ) {
-    if let Some(symbol) = symbol {
-        mod_records.push(ModRecordData::new(
-            *starting_address,
-            *mod_length,
-            symbol.to_string(),
-        ));
+    mod_records.push(Record::Modification {
+        address: *address,
+        half_bytes: *mod_length,
+        sign: symbol.map_or(Sign::Plus, |(sign, _)| *sign),
+        symbol: symbol.map(|(_, name)| name.clone()),
+    });
+}
+
+// the loader needs the D and R records before any text,
+// wherever EXTDEF, EXTREF or EXPORTS were in the source
+fn write_link_records(object_data: &mut ObjectData, link_records: &mut Vec<Record>) {
+    object_data.insert_after_header(link_records.drain(..));
+}
+
+fn write_mod_record(object_data: &mut ObjectData, mod_records: &mut Vec<Record>) {
+    for record in mod_records.drain(..) {
+        object_data.add_record(record);
     }
 }
 
-fn write_mod_record(object_data: &mut ObjectData, mod_records: &mut Vec<ModRecordData>) {
-    for record in mod_records {
-        object_data.add_mod_records(format!(
-            "M{:06X}{:02X}+{}\n",
-            record.starting_address(),
-            record.mod_length(),
-            record.symbol()
-        ));
+// Looks up a symbol, an undefined symbol is an error
+// pointing at the operand, with a suggestion if there's a close match
+fn lookup_symbol<'a>(
+    symtable: &'a SymbolTable,
+    line: &AssemblyLine,
+    name: &str,
+) -> Result<&'a Symbol, AssembleError> {
+    match symtable.get(name) {
+        Some(symbol) => Ok(symbol),
+        None => {
+            let err = line.error(ErrorKind::UndefinedSymbol, name);
+            match symtable.suggest(name) {
+                Some(suggestion) => Err(err.with_note(format!("did you mean '{}'?", suggestion))),
+                None => Err(err),
+            }
+        }
     }
 }
 
-fn find_symbol<'a>(symtable: &'a Vec<Symbol>, operand: &str) -> Option<&'a Symbol> {
-    let found_symbol = symtable.iter().position(|r| r.name() == operand);
+// looks up a mnemonic in the opcode table
+fn find_opcode<'a, 'b>(
+    opcodes_list: &'b [Instruction<'a>],
+    mnemonic: &str,
+) -> Option<&'b Instruction<'a>> {
+    opcodes_list.iter().find(|r| r.name() == mnemonic)
+}
 
-    if let Some(index) = found_symbol {
-        return symtable.get(index);
+// The bytes of a BYTE operand, one or more constants like C'HI',X'0A'
+fn byte_constants(line: &AssemblyLine) -> Result<Vec<u8>, AssembleError> {
+    let list = line.require_operand()?;
+    let mut bytes = vec![];
+    for constant in split_operands(list) {
+        // a stray comma, like BYTE C'HI',
+        if constant.is_empty() {
+            return Err(line.error(ErrorKind::InvalidOperand, list));
+        }
+        let (constant_type, contents) = parse_constant(line, constant)?;
+        bytes.extend(constant_bytes(constant_type, contents));
+    }
+    Ok(bytes)
+}
+
+// Splits a constant such as C'EOF' or X'F1' into its
+// type character and the part between the quotes
+fn parse_constant<'a>(
+    line: &AssemblyLine,
+    operand: &'a str,
+) -> Result<(char, &'a str), AssembleError> {
+    let invalid = || line.error(ErrorKind::InvalidOperand, operand);
+
+    let constant_type = match operand.chars().next() {
+        Some(c @ ('C' | 'X')) => c,
+        _ => return Err(invalid()),
+    };
+    let contents = match operand[1..]
+        .strip_prefix('\'')
+        .and_then(|op| op.strip_suffix('\''))
+    {
+        Some(contents) if !contents.is_empty() => contents,
+        _ => return Err(invalid()),
+    };
+
+    if constant_type == 'X'
+        && (contents.len() % 2 != 0 || !contents.chars().all(|c| c.is_ascii_hexdigit()))
+    {
+        return Err(invalid());
+    }
+    if constant_type == 'C' && !contents.is_ascii() {
+        return Err(invalid());
     }
 
-    None
+    Ok((constant_type, contents))
 }
-fn find_instruction<'a>(directive: &'a str, operand: &'a str) -> Option<Instruction<'a>> {
-    if is_directive(directive) {
-        match directive {
-            "RESB" | "RESW" => {
-                // This can be removed in favor of the wildcard
-                // do nothing
-            }
-            "WORD" => {
-                return Some(Instruction::new(
-                    directive,
-                    i32::from_str_radix(operand, 10).unwrap(),
-                ));
-            }
-            "BYTE" if operand.starts_with('X') => {
-                let len = operand.len() - 1;
-                let clean_operand = &operand[2..len];
-                return Some(Instruction::new(
-                    directive,
-                    i32::from_str_radix(clean_operand, 16).unwrap(),
-                ));
-            }
-            "BYTE" if operand.starts_with('C') => {
-                // Remove the stuff unneeded from the operand
-                let len = operand.len() - 1;
-                let clean_operand = &operand[2..len];
-                return Some(Instruction::new(
-                    directive,
-                    <i32>::from_str_radix(ascii_to_hex::get_hex_string(clean_operand).as_str(), 16)
-                        .unwrap(),
-                ));
-            }
-            _ => {}
-        }
+
+// the bytes of a constant that parse_constant has checked
+fn constant_bytes(constant_type: char, contents: &str) -> Vec<u8> {
+    if constant_type == 'X' {
+        (0..contents.len())
+            .step_by(2)
+            .filter_map(|i| u8::from_str_radix(&contents[i..i + 2], 16).ok())
+            .collect()
+    } else {
+        contents.bytes().collect()
     }
-    None
 }
 
 // Checks if line has a symbol
@@ -443,9 +1463,8 @@ fn find_instruction<'a>(directive: &'a str, operand: &'a str) -> Option<Instruct
 // 0: Symbol Line
 // 1: Non-symbol assembly Line
 // -1: Non-assembly line
-fn is_symbol_line(buffer: &str, counter: &mut i32) -> i32 {
+fn is_symbol_line(buffer: &str) -> i32 {
     if buffer.starts_with('\t') {
-        *counter += 3;
         return 1;
     } else if buffer.starts_with('#') {
         // do nothing
@@ -458,35 +1477,64 @@ fn is_symbol_line(buffer: &str, counter: &mut i32) -> i32 {
 }
 
 // returns the address increment
-fn get_address_increment(directive: &str, operand: Option<&str>) -> i32 {
+// RESB and RESW counts can be expressions, but they have to be
+// absolute and only use symbols defined further up
+fn get_address_increment(
+    line: &AssemblyLine,
+    symtable: &SymbolTable,
+    address_counter: i32,
+    opcodes_list: &[Instruction],
+) -> Result<i32, AssembleError> {
     let mut address_increment = 3;
-    if let Some(op) = operand {
-        match directive {
-            "RESB" => {
-                address_increment = op.parse::<i32>().unwrap();
-            }
-            "RESW" => {
-                address_increment = (op.parse::<i32>().unwrap()) * 3;
-            }
-            "BYTE" if op.starts_with('C') => {
-                let len = op.len() - 4;
-                address_increment = len as i32;
-            }
-            "BYTE" if op.starts_with('X') => {
-                let len = op.len() - 4;
-                address_increment = len as i32 / 2;
-            }
-            "END" => address_increment = 0,
-            _ => {}
+    match line.directive() {
+        "RESB" | "RESW" | "RESR" => {
+            let operand = line.require_operand()?;
+            let count = evaluate(operand, symtable, address_counter, line)?;
+            let count = match count.value() {
+                count_value
+                    if count_value >= 0 && count.relocatability() == Relocatability::Absolute =>
+                {
+                    count_value
+                }
+                _ => return Err(line.error(ErrorKind::InvalidOperand, operand)),
+            };
+            // words are 3 bytes, floats (reals) are 6
+            let size = match line.directive() {
+                "RESW" => 3,
+                "RESR" => 6,
+                _ => 1,
+            };
+            address_increment = match count.checked_mul(size) {
+                Some(increment) => increment,
+                None => return Err(line.error(ErrorKind::MemoryOutOfBounds, operand)),
+            };
         }
+        "REAL" => address_increment = 6,
+        "WORD" => address_increment = 3 * split_operands(line.require_operand()?).len() as i32,
+        "BYTE" => address_increment = byte_constants(line)?.len() as i32,
+        "END" | "SET" | "EQU" | "ORG" | "LTORG" | "BASE" | "NOBASE" | "USE" | "CSECT"
+        | "EXTDEF" | "EXTREF" | "EXPORTS" => address_increment = 0,
+        directive if !is_directive(directive) => {
+            address_increment = instruction_length(opcodes_list, directive)
+        }
+        _ => {}
     }
 
-    address_increment
+    Ok(address_increment)
+}
+
+// How many bytes an instruction takes up, going by its format
+// unknown mnemonics are reported in pass 2, so they just get 3
+fn instruction_length(opcodes_list: &[Instruction], mnemonic: &str) -> i32 {
+    if mnemonic.starts_with('+') {
+        return 4;
+    }
+    find_opcode(opcodes_list, mnemonic).map_or(3, |instruction| *instruction.format())
 }
 
 // initializes the opcodes for the SIC machine
 // This WILL be painful to read
-fn initalize_opcodes(opcodes_list: &mut Vec<Instruction>) {
+fn initalize_opcodes(opcodes_list: &mut Vec<Instruction>, target: Target) {
     let instructions = vec![
         "ADD", "AND", "COMP", "DIV", "J", "JEQ", "JGT", "JLT", "JSUB", "LDA", "LDCH", "LDL", "LDX",
         "MUL", "OR", "RD", "RSUB", "STA", "STCH", "STL", "STSW", "STX", "SUB", "TD", "TIX", "WD",
@@ -498,27 +1546,86 @@ fn initalize_opcodes(opcodes_list: &mut Vec<Instruction>) {
 
     // because instructions and opcodes are both the same length,
     // we just need the length of one for this loop to connect them
-    for i in 0..instructions.len() {
-        opcodes_list.push(Instruction::new(
-            instructions[i],
-            i32::from_str_radix(opcodes[i], 16).ok().unwrap(),
-        ));
+    for (instruction, opcode) in instructions.into_iter().zip(opcodes) {
+        if let Ok(opcode) = i32::from_str_radix(opcode, 16) {
+            opcodes_list.push(Instruction::new(instruction, opcode, 3));
+        }
+    }
+
+    if target == Target::Sic {
+        return;
     }
+
+    // SIC/XE adds these on top, with the format each one uses
+    let instructions = vec![
+        ("ADDF", "58", 3),
+        ("ADDR", "90", 2),
+        ("CLEAR", "B4", 2),
+        ("COMPF", "88", 3),
+        ("COMPR", "A0", 2),
+        ("DIVF", "64", 3),
+        ("DIVR", "9C", 2),
+        ("FIX", "C4", 1),
+        ("FLOAT", "C0", 1),
+        ("HIO", "F4", 1),
+        ("LDB", "68", 3),
+        ("LDF", "70", 3),
+        ("LDS", "6C", 3),
+        ("LDT", "74", 3),
+        ("LPS", "D0", 3),
+        ("MULF", "60", 3),
+        ("MULR", "98", 2),
+        ("NORM", "C8", 1),
+        ("RMO", "AC", 2),
+        ("SHIFTL", "A4", 2),
+        ("SHIFTR", "A8", 2),
+        ("SIO", "F0", 1),
+        ("SSK", "EC", 3),
+        ("STB", "78", 3),
+        ("STF", "80", 3),
+        ("STI", "D4", 3),
+        ("STS", "7C", 3),
+        ("STT", "84", 3),
+        ("SUBF", "5C", 3),
+        ("SUBR", "94", 2),
+        ("SVC", "B0", 2),
+        ("TIO", "F8", 1),
+        ("TIXR", "B8", 2),
+    ];
+    for (instruction, opcode, format) in instructions {
+        if let Ok(opcode) = i32::from_str_radix(opcode, 16) {
+            opcodes_list.push(Instruction::new(instruction, opcode, format));
+        }
+    }
+}
+
+// Writes <filename>.exports, one exported symbol per line:
+// section, name and address (hex), separated by tabs
+fn write_exports(program: &ObjectProgram, filename: &str) -> ioResult<()> {
+    let exports = program.exports();
+    if exports.is_empty() {
+        return Ok(());
+    }
+
+    let mut exports_file = fs::File::create(format!("{}.exports", filename))?;
+    for symbol in exports {
+        writeln!(
+            exports_file,
+            "{}\t{}\t{:06X}",
+            symbol.section(),
+            symbol.name(),
+            symbol.address()
+        )?;
+    }
+    Ok(())
 }
 
-fn write_to_file(object_data: &ObjectData, filename: String) -> ioResult<()> {
+fn write_to_file(program: &ObjectProgram, filename: String) -> ioResult<()> {
     let mut output_file = fs::File::create(format!("{}.obj", filename))?;
-    output_file.write_all(object_data.head_record().as_bytes())?;
-    print!("{}", object_data.head_record());
-    for t_record in object_data.text_records() {
-        output_file.write_all(t_record.as_bytes())?;
-        print!("{}", t_record);
-    }
-    for m_record in object_data.mod_records() {
-        output_file.write_all(m_record.as_bytes())?;
-        print!("{}", m_record);
-    }
-    output_file.write_all(object_data.end_record().as_bytes())?;
-    print!("{}", object_data.end_record());
+    output_file.write_all(program.to_string().as_bytes())?;
+    let mut listing_file = fs::File::create(format!("{}.lst", filename))?;
+    listing_file.write_all(program.listing().to_string().as_bytes())?;
+    write_exports(program, &filename)?;
+    print!("{}", program);
     Ok(())
 }
@@ -0,0 +1,115 @@
+// Program listing
+// Every source line next to its address and object code,
+// plus the lines the assembler adds itself (literal pools).
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct ListingLine {
+    line_number: Option<usize>, // None for lines the assembler made up
+    address: Option<i32>,
+    source: String,
+    object_code: Vec<u8>,
+}
+
+impl ListingLine {
+    pub fn line_number(&self) -> Option<usize> {
+        self.line_number
+    }
+    pub fn address(&self) -> Option<i32> {
+        self.address
+    }
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+    pub fn object_code(&self) -> &Vec<u8> {
+        &self.object_code
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Listing {
+    lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    pub fn new() -> Self {
+        Self { lines: Vec::new() }
+    }
+
+    pub fn lines(&self) -> &Vec<ListingLine> {
+        &self.lines
+    }
+
+    pub fn add_line(
+        &mut self,
+        line_number: Option<usize>,
+        address: Option<i32>,
+        source: &str,
+        object_code: Vec<u8>,
+    ) {
+        self.lines.push(ListingLine {
+            line_number,
+            address,
+            source: source.to_string(),
+            object_code,
+        });
+    }
+
+    // adds the lines of another listing to the end of this one
+    pub fn append(&mut self, other: Listing) {
+        self.lines.extend(other.lines);
+    }
+
+    // fills in the object code of the line added last
+    pub fn set_object_code(&mut self, object_code: Vec<u8>) {
+        if let Some(line) = self.lines.last_mut() {
+            line.object_code = object_code;
+        }
+    }
+}
+
+// line number, address, source, object code
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            let line_number = match line.line_number {
+                Some(number) => format!("{:>5}", number),
+                None => String::new(),
+            };
+            let address = match line.address {
+                Some(address) => format!("{:04X}", address),
+                None => String::new(),
+            };
+            let source = expand_tabs(&line.source);
+            let object_code: String = line
+                .object_code
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+
+            let text = format!(
+                "{:>5}  {:<4}  {:<40}{}",
+                line_number, address, source, object_code
+            );
+            writeln!(f, "{}", text.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+// tabs would throw the object code column around,
+// so line them up on 8 column tab stops with spaces
+fn expand_tabs(source: &str) -> String {
+    let mut expanded = String::new();
+    for c in source.chars() {
+        if c == '\t' {
+            expanded.push(' ');
+            while !expanded.len().is_multiple_of(8) {
+                expanded.push(' ');
+            }
+        } else {
+            expanded.push(c);
+        }
+    }
+    expanded
+}
@@ -0,0 +1,119 @@
+// Literal table
+// Literals (=C'EOF', =X'05', =1000) are constants written right in the operand.
+// They're collected into pools that get placed at LTORG or at END.
+use crate::errors::{AssembleError, ErrorKind};
+use crate::{constant_bytes, parse_constant, word_to_bytes, AssemblyLine};
+
+#[derive(Debug, Clone)]
+pub struct Literal {
+    name: String,         // the literal as written, like =C'EOF'
+    value: Vec<u8>,       // the bytes it stands for
+    pool: usize,          // which LTORG (or END) places it
+    block: usize,         // program block the pool is placed in
+    address: Option<i32>, // known once its pool is placed
+}
+
+impl Literal {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn value(&self) -> &Vec<u8> {
+        &self.value
+    }
+    pub fn address(&self) -> Option<i32> {
+        self.address
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LiteralTable {
+    literals: Vec<Literal>,
+    current_pool: usize, // pool that new literals go into
+}
+
+impl LiteralTable {
+    pub fn new() -> Self {
+        Self {
+            literals: Vec::new(),
+            current_pool: 0,
+        }
+    }
+
+    // Adds a literal to the current pool
+    // Literals with the same value share a spot, even if written differently
+    pub fn add(&mut self, name: &str, value: Vec<u8>) {
+        if self.find(&value, self.current_pool).is_none() {
+            self.literals.push(Literal {
+                name: name.to_string(),
+                value,
+                pool: self.current_pool,
+                block: 0,
+                address: None,
+            });
+        }
+    }
+
+    // the literal with this value in a pool
+    pub fn find(&self, value: &[u8], pool: usize) -> Option<&Literal> {
+        self.literals
+            .iter()
+            .find(|literal| literal.pool == pool && literal.value == value)
+    }
+
+    // Gives every literal in the current pool an address, starting at
+    // address_counter in a program block, and starts a new pool
+    // returns the address counter after the pool
+    pub fn place_pool(&mut self, address_counter: i32, block: usize) -> i32 {
+        let mut address_counter = address_counter;
+        for literal in self
+            .literals
+            .iter_mut()
+            .filter(|literal| literal.pool == self.current_pool)
+        {
+            literal.address = Some(address_counter);
+            literal.block = block;
+            address_counter += literal.value.len() as i32;
+        }
+        self.current_pool += 1;
+        address_counter
+    }
+
+    // same as SymbolTable::move_block, for literals placed in a program block
+    pub fn move_block(&mut self, block: usize, offset: i32) {
+        for literal in self.literals.iter_mut().filter(|l| l.block == block) {
+            literal.address = literal.address.map(|address| address + offset);
+        }
+    }
+
+    // the literals placed by a pool, in address order
+    pub fn pool(&self, pool: usize) -> Vec<&Literal> {
+        self.literals
+            .iter()
+            .filter(|literal| literal.pool == pool)
+            .collect()
+    }
+
+    // true if the current pool has literals waiting for a place
+    pub fn has_pending(&self) -> bool {
+        !self.pool(self.current_pool).is_empty()
+    }
+}
+
+pub fn is_literal(operand: &str) -> bool {
+    operand.starts_with('=')
+}
+
+// The bytes a literal stands for
+// =C'..' and =X'..' work like BYTE, =1000 like WORD
+pub fn parse_literal(literal: &str, line: &AssemblyLine) -> Result<Vec<u8>, AssembleError> {
+    let constant = &literal[1..];
+    if constant.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return match constant.parse::<i32>() {
+            Ok(value) => Ok(word_to_bytes(value)),
+            Err(_) => Err(line.error(ErrorKind::InvalidOperand, literal)),
+        };
+    }
+
+    let (constant_type, contents) = parse_constant(line, constant)?;
+    Ok(constant_bytes(constant_type, contents))
+}
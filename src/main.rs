@@ -11,13 +11,21 @@ use sic_assembler::{self, Config};
 fn main() {
     // establishing variables
     let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Error: {}", err);
+        eprintln!("{}", err);
         process::exit(1);
     });
 
-    if let Err(e) = sic_assembler::run(config) {
-        eprintln!("Error during execution: {}", e);
+    match sic_assembler::run(config) {
+        // warnings don't stop the object file from being written
+        Ok(program) => {
+            if !program.warnings().is_empty() {
+                eprintln!("{}", program.warnings());
+            }
+        }
+        Err(diagnostics) => {
+            eprintln!("{}", diagnostics);
 
-        process::exit(1);
+            process::exit(1);
+        }
     }
 }
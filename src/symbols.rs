@@ -1,14 +1,57 @@
 // Symbol structure
-#[derive(Debug)]
+use std::collections::HashMap;
+
+// How a symbol got defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Label,    // label in front of an instruction or directive
+    Equ,      // named value from EQU
+    Set,      // named value from SET, can be redefined
+    External, // defined in another control section
+}
+
+// Whether the loader has to move the value along with the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relocatability {
+    Absolute,
+    Relative,
+}
+
+#[derive(Debug, Clone)]
 pub struct Symbol {
     name: String,
     address: i32,
+    kind: SymbolKind,
+    relocatability: Relocatability,
+    line: usize,            // line the symbol was (first) defined on
+    section: String,        // control section the symbol belongs to
+    block: usize,           // program block the symbol belongs to
+    exported: bool,         // named by EXPORTS or EXTDEF, so other programs can use it
+    references: Vec<usize>, // lines that use the symbol
 }
 
 impl Symbol {
     // standard new method
-    pub fn new(name: String, address: i32) -> Self {
-        Symbol { name, address }
+    pub fn new(
+        name: String,
+        address: i32,
+        kind: SymbolKind,
+        relocatability: Relocatability,
+        line: usize,
+        section: String,
+        block: usize,
+    ) -> Self {
+        Symbol {
+            name,
+            address,
+            kind,
+            relocatability,
+            line,
+            section,
+            block,
+            exported: false,
+            references: Vec::new(),
+        }
     }
 
     // get name
@@ -20,4 +63,156 @@ impl Symbol {
     pub fn address(&self) -> &i32 {
         &self.address
     }
+
+    pub fn set_address(&mut self, address: i32, relocatability: Relocatability) {
+        self.address = address;
+        self.relocatability = relocatability;
+    }
+
+    pub fn kind(&self) -> SymbolKind {
+        self.kind
+    }
+
+    pub fn relocatability(&self) -> Relocatability {
+        self.relocatability
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn section(&self) -> &str {
+        &self.section
+    }
+
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    pub fn set_block(&mut self, block: usize) {
+        self.block = block;
+    }
+
+    pub fn exported(&self) -> bool {
+        self.exported
+    }
+
+    pub fn export(&mut self) {
+        self.exported = true;
+    }
+
+    pub fn references(&self) -> &Vec<usize> {
+        &self.references
+    }
+
+    // only SET symbols can be given a new value
+    pub fn redefinable(&self) -> bool {
+        self.kind == SymbolKind::Set
+    }
+}
+
+// Symbol table, keyed by name
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.symbols.get_mut(name)
+    }
+
+    // adds a symbol, replacing any symbol with the same name
+    pub fn insert(&mut self, symbol: Symbol) {
+        self.symbols.insert(symbol.name().to_string(), symbol);
+    }
+
+    // notes that a line uses the symbol, if it exists
+    pub fn add_reference(&mut self, name: &str, line: usize) {
+        if let Some(symbol) = self.symbols.get_mut(name) {
+            if !symbol.references.contains(&line) {
+                symbol.references.push(line);
+            }
+        }
+    }
+
+    // Turns the block addresses of a program block into real addresses
+    // once pass 1 knows where the block goes
+    pub fn move_block(&mut self, block: usize, offset: i32) {
+        for symbol in self.symbols.values_mut() {
+            if symbol.block == block && symbol.relocatability == Relocatability::Relative {
+                symbol.address += offset;
+            }
+        }
+    }
+
+    // every symbol, sorted by name
+    pub fn symbols(&self) -> Vec<&Symbol> {
+        let mut symbols: Vec<&Symbol> = self.symbols.values().collect();
+        symbols.sort_by(|a, b| a.name().cmp(b.name()));
+        symbols
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    // Finds the symbol with the name closest to a misspelled one
+    // Used to suggest a fix when an operand isn't defined
+    pub fn suggest(&self, name: &str) -> Option<&str> {
+        // anything further away than this is probably not a typo
+        let max_distance = (name.len() / 3).max(1);
+
+        self.symbols
+            .keys()
+            .map(|symbol| (edit_distance(symbol, name), symbol.as_str()))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min()
+            .map(|(_, name)| name)
+    }
+}
+
+// Edit distance between two names, counting swapped
+// neighbouring letters as a single typo
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distance[i][j] is the distance between a[..i] and b[..j]
+    let mut distance = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distance.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in distance[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (distance[i - 1][j] + 1)
+                .min(distance[i][j - 1] + 1)
+                .min(distance[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(distance[i - 2][j - 2] + 1);
+            }
+            distance[i][j] = best;
+        }
+    }
+
+    distance[a.len()][b.len()]
 }